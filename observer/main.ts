@@ -1,7 +1,7 @@
 import { captureScreen, pngDimensions } from "./src/capture.ts";
 import { analyzeScreen } from "./src/vlm.ts";
 import { createStateMachine } from "./src/state.ts";
-import { createIpcServer } from "./src/ipc.ts";
+import { createIpcServer, type RendererMessage } from "./src/ipc.ts";
 import { config } from "./config.ts";
 
 const abortController = new AbortController();
@@ -27,7 +27,31 @@ async function main() {
   console.log(`  VLM model: ${config.vlmModel}`);
 
   const stateMachine = createStateMachine();
-  const ipc = await createIpcServer(config.socketPath);
+  let rendererActivity: string | null = null;
+  const onRendererMessage = (message: RendererMessage) => {
+    if (message.type === "hello") {
+      const outputs = message.outputs.map((o) => `${o.name} ${o.width}x${o.height}`).join(", ");
+      console.log(
+        `Renderer connected (protocol v${message.version}, outputs: ${outputs || "none yet"})`,
+      );
+      rendererActivity = null;
+    } else if (message.type === "error") {
+      console.warn(`Renderer rejected a message (${message.code}): ${message.message}`);
+    } else {
+      const activity = message.held ? "held" : message.paused ? "paused" : "running";
+      if (activity !== rendererActivity) {
+        rendererActivity = activity;
+        console.log(
+          activity === "held"
+            ? "Renderer paused by a client"
+            : activity === "paused"
+            ? "Renderer paused (no output visible)"
+            : `Renderer showing ${message.state} at ${message.fps.toFixed(0)} fps`,
+        );
+      }
+    }
+  };
+  const ipc = await createIpcServer(config.socketPath, onRendererMessage);
   let loggedCaptureResolution = false;
 
   try {
@@ -1,11 +1,64 @@
-import type { StateUpdate } from "./state.ts";
+import type { EntityState, StateUpdate } from "./state.ts";
+
+/** Highest protocol version the observer speaks; both sides use the lower of their two. */
+export const PROTOCOL_VERSION = 1;
+/** Oldest version the observer still speaks; renderers offering less are turned away. */
+export const MIN_PROTOCOL_VERSION = 1;
+/** Message types the observer accepts from renderers. */
+const ACCEPTED_MESSAGES = ["hello", "status", "error"];
+
+export interface RendererOutput {
+  name: string;
+  width: number;
+  height: number;
+}
+
+/** Sent by a renderer right after it connects. */
+export interface RendererHello {
+  type: "hello";
+  version: number;
+  /** Message types the renderer accepts. */
+  messages: string[];
+  outputs: RendererOutput[];
+}
+
+/** Sent by a renderer about once a second while connected. */
+export interface RendererStatus {
+  type: "status";
+  state: EntityState;
+  target_state: EntityState;
+  blend: number;
+  intensity: number;
+  fps: number;
+  /** Suspended because no output is visible. */
+  paused: boolean;
+  /** Frozen by a `pause` message. */
+  held: boolean;
+  outputs: RendererOutput[];
+}
+
+export type ErrorCode = "invalid_message" | "unknown_type" | "unsupported_version";
+
+/** Reply to a message the other side could not act on. Both sides send these. */
+export interface ProtocolError {
+  type: "error";
+  code: ErrorCode;
+  message: string;
+  /** `type` of the rejected message, if it had one. */
+  request_type?: string;
+}
+
+export type RendererMessage = RendererHello | RendererStatus | ProtocolError;
 
 export interface IpcServer {
   broadcast(update: StateUpdate): void;
   close(): void;
 }
 
-export async function createIpcServer(socketPath: string): Promise<IpcServer> {
+export async function createIpcServer(
+  socketPath: string,
+  onMessage: (message: RendererMessage) => void = () => {},
+): Promise<IpcServer> {
   try {
     await Deno.remove(socketPath);
   } catch (err) {
@@ -22,6 +75,7 @@ export async function createIpcServer(socketPath: string): Promise<IpcServer> {
     try {
       for await (const conn of listener) {
         clients.add(conn);
+        void send(conn, { type: "hello", version: PROTOCOL_VERSION, messages: ACCEPTED_MESSAGES });
         void handleClient(conn);
       }
     } catch (err) {
@@ -41,6 +95,23 @@ export async function createIpcServer(socketPath: string): Promise<IpcServer> {
     }
   }
 
+  function send(conn: Deno.Conn, message: object): Promise<void> {
+    const payload = encoder.encode(`${JSON.stringify(message)}\n`);
+    const prev = writeChains.get(conn) ?? Promise.resolve();
+    const next = prev
+      .then(() => writeAll(conn, payload))
+      .catch(() => dropClient(conn));
+    writeChains.set(conn, next);
+    return next;
+  }
+
+  function reject(conn: Deno.Conn, code: ErrorCode, message: string, requestType?: string) {
+    console.warn(`IPC: rejected renderer message: ${message}`);
+    const error: ProtocolError = { type: "error", code, message };
+    if (requestType !== undefined) error.request_type = requestType;
+    return send(conn, error);
+  }
+
   function dropClient(conn: Deno.Conn): void {
     clients.delete(conn);
     writeChains.delete(conn);
@@ -51,29 +122,71 @@ export async function createIpcServer(socketPath: string): Promise<IpcServer> {
     }
   }
 
+  // Unknown fields are ignored; unknown types and malformed messages get an `error` reply.
+  // Returns false if the connection should be closed.
+  function handleLine(conn: Deno.Conn, line: string): boolean {
+    if (!line.trim()) return true;
+    let message: unknown;
+    try {
+      message = JSON.parse(line);
+    } catch (err) {
+      void reject(conn, "invalid_message", `invalid JSON: ${err}`);
+      return true;
+    }
+    const type = (message as { type?: unknown } | null)?.type;
+    if (typeof type !== "string") {
+      void reject(conn, "invalid_message", "missing `type`");
+      return true;
+    }
+    if (!ACCEPTED_MESSAGES.includes(type)) {
+      void reject(conn, "unknown_type", `unknown message type \`${type}\``, type);
+      return true;
+    }
+    if (type === "hello") {
+      const version = (message as { version?: unknown }).version;
+      if (typeof version !== "number") {
+        void reject(conn, "invalid_message", "invalid `hello` message: missing `version`", type);
+        return true;
+      }
+      if (version < MIN_PROTOCOL_VERSION) {
+        const reply = reject(
+          conn,
+          "unsupported_version",
+          `protocol version ${version} is not supported, need at least ${MIN_PROTOCOL_VERSION}`,
+          type,
+        );
+        void reply.finally(() => dropClient(conn));
+        return false;
+      }
+    }
+    onMessage(message as RendererMessage);
+    return true;
+  }
+
+  // Renderers send newline-delimited JSON, like the updates broadcast to them.
   async function handleClient(conn: Deno.Conn): Promise<void> {
+    const decoder = new TextDecoder();
+    let pending = "";
     try {
-      const buffer = new Uint8Array(1024);
+      const buffer = new Uint8Array(4096);
       while (true) {
         const read = await conn.read(buffer);
         if (read === null) break;
+        pending += decoder.decode(buffer.subarray(0, read), { stream: true });
+        const lines = pending.split("\n");
+        pending = lines.pop() ?? "";
+        if (!lines.every((line) => handleLine(conn, line))) return;
       }
     } catch {
-      // Ignore placeholder errors.
-    } finally {
-      dropClient(conn);
+      // The connection failed; dropping the client is all there is to do.
     }
+    dropClient(conn);
   }
 
   return {
     broadcast(update: StateUpdate) {
-      const payload = encoder.encode(`${JSON.stringify(update)}\n`);
       for (const conn of clients) {
-        const prev = writeChains.get(conn) ?? Promise.resolve();
-        const next = prev
-          .then(() => writeAll(conn, payload))
-          .catch(() => dropClient(conn));
-        writeChains.set(conn, next);
+        void send(conn, update);
       }
     },
     close() {
@@ -0,0 +1,185 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use log::{info, warn};
+
+use crate::gpu::{GpuContext, Image, OutputTarget};
+
+/// Write every `every`-th frame into `dir` as `frame-NNNNNN.png`.
+#[derive(Debug, Clone)]
+pub struct SequenceOptions {
+    pub dir: PathBuf,
+    pub every: u32,
+}
+
+/// Most screenshot requests queued for one frame; each one stalls the frame on a readback.
+const MAX_PENDING: usize = 4;
+
+/// Collects screenshot requests and image-sequence output, and services them after a frame
+/// has been rendered. Reading a frame back stalls until the GPU finishes it, so captures are
+/// only taken when something asked for one.
+#[derive(Debug, Default)]
+pub struct FrameCapture {
+    sequence: Option<SequenceOptions>,
+    pending: Vec<PathBuf>,
+}
+
+impl FrameCapture {
+    pub fn new(sequence: Option<SequenceOptions>) -> anyhow::Result<Self> {
+        if let Some(sequence) = &sequence {
+            std::fs::create_dir_all(&sequence.dir).with_context(|| {
+                format!(
+                    "failed to create capture directory {}",
+                    sequence.dir.display()
+                )
+            })?;
+            info!(
+                "Capturing every {} frame(s) to {}",
+                sequence.every,
+                sequence.dir.display()
+            );
+        }
+        Ok(Self {
+            sequence,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Queues a screenshot of the next rendered frame into `SENTINEL_CAPTURE_DIR` (or the
+    /// system temp directory), named `file_name` or else timestamped. The file must not exist
+    /// yet. Repeats of a queued request are merged, and requests beyond [`MAX_PENDING`]
+    /// dropped.
+    pub fn request(&mut self, file_name: Option<&Path>) {
+        let dir = capture_dir();
+        let path = match file_name {
+            Some(file_name) => dir.join(file_name),
+            None => {
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or_default();
+                dir.join(format!("sentinel-{millis}.png"))
+            }
+        };
+        if self.pending.contains(&path) {
+            return;
+        }
+        if self.pending.len() >= MAX_PENDING {
+            warn!(
+                "Dropping capture request for {}: {MAX_PENDING} already waiting for the next frame",
+                path.display()
+            );
+            return;
+        }
+        self.pending.push(path);
+    }
+
+    /// Services pending captures from the frame just rendered to `outputs`. With more than
+    /// one output, each gets its own file with the output name added to the file stem.
+    pub fn after_frame(
+        &mut self,
+        gpu: &GpuContext,
+        outputs: &[(&str, &OutputTarget)],
+        frame_count: u32,
+    ) {
+        // Requested screenshots never replace an existing file; the sequence overwrites
+        // frames left from an earlier run.
+        let mut paths: Vec<(PathBuf, bool)> = std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|path| (path, false))
+            .collect();
+        if let Some(sequence) = &self.sequence {
+            if frame_count % sequence.every == 0 {
+                let path = sequence.dir.join(format!("frame-{frame_count:06}.png"));
+                paths.push((path, true));
+            }
+        }
+        if paths.is_empty() {
+            return;
+        }
+
+        for (name, output) in outputs {
+            let image = match gpu.read_frame(output) {
+                Ok(image) => image,
+                Err(err) => {
+                    warn!("Frame capture failed: {err:#}");
+                    continue;
+                }
+            };
+            for (path, overwrite) in &paths {
+                let path = if outputs.len() > 1 {
+                    with_output_name(path, name)
+                } else {
+                    path.clone()
+                };
+                let written = if *overwrite {
+                    write_png(&path, &image)
+                } else {
+                    write_new_png(&path, &image)
+                };
+                match written {
+                    Ok(()) => info!("Captured frame {frame_count} to {}", path.display()),
+                    Err(err) => warn!("Failed to write {}: {err:#}", path.display()),
+                }
+            }
+        }
+    }
+}
+
+pub fn write_png(path: &Path, image: &Image) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    encode_png(file, image)
+}
+
+/// Like [`write_png`], but fails rather than replace an existing file.
+fn write_new_png(path: &Path, image: &Image) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    encode_png(file, image)
+}
+
+fn encode_png(file: File, image: &Image) -> anyhow::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.rgba)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// `shot.png` → `shot-DP-1.png`.
+fn with_output_name(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{stem}-{name}");
+    if let Some(extension) = path.extension() {
+        file_name.push('.');
+        file_name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
+fn capture_dir() -> PathBuf {
+    std::env::var_os("SENTINEL_CAPTURE_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
@@ -0,0 +1,333 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::{capture::SequenceOptions, config::OutputsConfig};
+
+pub const USAGE: &str = "\
+Usage: sentinel-renderer [OPTIONS]
+       sentinel-renderer ctl <COMMAND>  Drive a running renderer (see `ctl --help`)
+
+Options:
+  --config <PATH>     Config file [default: $XDG_CONFIG_HOME/sentinel/renderer.toml]
+  --print-default-config
+                      Print the built-in configuration as TOML and exit
+  --headless          Render offscreen without a Wayland compositor
+  --frames <N>        Number of frames to simulate in headless mode [default: 300]
+  --size <WxH>        Offscreen render size in headless mode, at most 8192x8192
+                      [default: 1280x720]
+  --software          Use a software (fallback) GPU adapter in headless mode
+  --screenshot <PATH> Write the last headless frame to a PNG file
+  --capture-dir <DIR> Write rendered frames to DIR as a numbered PNG sequence
+  --capture-every <N> Only write every Nth frame to the sequence [default: 1]
+  --output <NAME>     Only draw on outputs matching NAME (repeatable); replaces
+                      `outputs.include` from the config
+  --exclude-output <NAME>
+                      Never draw on outputs matching NAME (repeatable); replaces
+                      `outputs.exclude` from the config
+  --shader-dir <DIR>  Load the backend's shaders (simulation.wgsl, render.wgsl and
+                      entity.wgsl by default) from DIR and reload them when they
+                      change (development)
+  -h, --help          Print this help";
+
+const DEFAULT_HEADLESS_FRAMES: u32 = 300;
+const DEFAULT_HEADLESS_SIZE: (u32, u32) = (1280, 720);
+/// Largest `--size` side: wgpu's default texture size limit. Adapters with only the
+/// downlevel limits may allow less, which the headless run checks once it has one.
+const MAX_HEADLESS_SIZE: u32 = 8192;
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub help: bool,
+    pub print_default_config: bool,
+    pub config: Option<PathBuf>,
+    pub headless: Option<HeadlessOptions>,
+    pub capture: Option<SequenceOptions>,
+    pub shader_dir: Option<PathBuf>,
+    pub outputs: OutputArgs,
+}
+
+/// `--output` and `--exclude-output`, which take precedence over the config file.
+#[derive(Debug, Default, Clone)]
+pub struct OutputArgs {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl OutputArgs {
+    pub fn apply(&self, outputs: &mut OutputsConfig) {
+        if !self.include.is_empty() {
+            outputs.include.clone_from(&self.include);
+        }
+        if !self.exclude.is_empty() {
+            outputs.exclude.clone_from(&self.exclude);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    pub frames: u32,
+    pub width: u32,
+    pub height: u32,
+    pub software: bool,
+    pub screenshot: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut help = false;
+        let mut print_default_config = false;
+        let mut config = None;
+        let mut headless = false;
+        let mut frames = None;
+        let mut size = None;
+        let mut software = false;
+        let mut screenshot = None;
+        let mut capture_dir = None;
+        let mut capture_every = None;
+        let mut shader_dir = None;
+        let mut outputs = OutputArgs::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => help = true,
+                "--print-default-config" => print_default_config = true,
+                "--config" => config = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--headless" => headless = true,
+                "--software" => software = true,
+                "--frames" => {
+                    let value = next_value(&mut args, &arg)?;
+                    frames = Some(
+                        value
+                            .parse::<u32>()
+                            .with_context(|| format!("invalid --frames value {value:?}"))?,
+                    );
+                }
+                "--size" => {
+                    let value = next_value(&mut args, &arg)?;
+                    size = Some(parse_size(&value)?);
+                }
+                "--screenshot" => {
+                    screenshot = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--capture-dir" => {
+                    capture_dir = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--capture-every" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let every = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| anyhow!("invalid --capture-every value {value:?}"))?;
+                    capture_every = Some(every);
+                }
+                "--shader-dir" => {
+                    shader_dir = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--output" => outputs.include.push(next_value(&mut args, &arg)?),
+                "--exclude-output" => outputs.exclude.push(next_value(&mut args, &arg)?),
+                other => bail!("unknown argument {other:?}"),
+            }
+        }
+
+        if !headless && (frames.is_some() || size.is_some() || software || screenshot.is_some()) {
+            bail!("--frames, --size, --software and --screenshot require --headless");
+        }
+        if capture_every.is_some() && capture_dir.is_none() {
+            bail!("--capture-every requires --capture-dir");
+        }
+
+        let headless = headless.then(|| {
+            let (width, height) = size.unwrap_or(DEFAULT_HEADLESS_SIZE);
+            HeadlessOptions {
+                frames: frames.unwrap_or(DEFAULT_HEADLESS_FRAMES),
+                width,
+                height,
+                software,
+                screenshot,
+            }
+        });
+        let capture = capture_dir.map(|dir| SequenceOptions {
+            dir,
+            every: capture_every.unwrap_or(1),
+        });
+
+        Ok(Self {
+            help,
+            print_default_config,
+            config,
+            headless,
+            capture,
+            shader_dir,
+            outputs,
+        })
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("{flag} requires a value"))
+}
+
+fn parse_size(value: &str) -> anyhow::Result<(u32, u32)> {
+    let (w, h) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| anyhow!("invalid --size value {value:?}; expected WIDTHxHEIGHT"))?;
+    let width = w
+        .parse::<u32>()
+        .with_context(|| format!("invalid --size width {w:?}"))?;
+    let height = h
+        .parse::<u32>()
+        .with_context(|| format!("invalid --size height {h:?}"))?;
+    if width == 0 || height == 0 {
+        bail!("--size must be non-zero, got {value:?}");
+    }
+    if width > MAX_HEADLESS_SIZE || height > MAX_HEADLESS_SIZE {
+        bail!("--size must be at most {MAX_HEADLESS_SIZE}x{MAX_HEADLESS_SIZE}, got {value:?}");
+    }
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Options> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_err(args: &[&str]) -> String {
+        format!("{:#}", parse(args).expect_err("parse should fail"))
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("1280x720").unwrap(), (1280, 720));
+        assert_eq!(parse_size("64X48").unwrap(), (64, 48));
+        assert_eq!(parse_size("8192x8192").unwrap(), (8192, 8192));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        for value in [
+            "1280",
+            "x720",
+            "1280x",
+            "-1x5",
+            "1.5x2",
+            "axb",
+            "1280x720x3",
+        ] {
+            assert!(parse_size(value).is_err(), "{value:?} should be rejected");
+        }
+        assert_eq!(
+            format!("{:#}", parse_size("0x720").unwrap_err()),
+            "--size must be non-zero, got \"0x720\""
+        );
+        assert_eq!(
+            format!("{:#}", parse_size("100000x100000").unwrap_err()),
+            "--size must be at most 8192x8192, got \"100000x100000\""
+        );
+        assert!(parse_size("8193x10").is_err());
+    }
+
+    #[test]
+    fn headless_defaults() {
+        let options = parse(&["--headless"]).unwrap();
+        let headless = options.headless.unwrap();
+        assert_eq!(headless.frames, DEFAULT_HEADLESS_FRAMES);
+        assert_eq!((headless.width, headless.height), DEFAULT_HEADLESS_SIZE);
+        assert!(!headless.software);
+        assert!(headless.screenshot.is_none());
+        assert!(options.capture.is_none());
+    }
+
+    #[test]
+    fn headless_flags() {
+        let options = parse(&[
+            "--headless",
+            "--frames",
+            "10",
+            "--size",
+            "320x200",
+            "--software",
+            "--screenshot",
+            "out.png",
+            "--capture-dir",
+            "frames",
+            "--capture-every",
+            "5",
+        ])
+        .unwrap();
+        let headless = options.headless.unwrap();
+        assert_eq!(headless.frames, 10);
+        assert_eq!((headless.width, headless.height), (320, 200));
+        assert!(headless.software);
+        assert_eq!(headless.screenshot, Some(PathBuf::from("out.png")));
+        let capture = options.capture.unwrap();
+        assert_eq!((capture.dir, capture.every), (PathBuf::from("frames"), 5));
+    }
+
+    #[test]
+    fn headless_only_flags() {
+        for args in [
+            &["--frames", "10"][..],
+            &["--size", "320x200"],
+            &["--software"],
+            &["--screenshot", "out.png"],
+        ] {
+            assert_eq!(
+                parse_err(args),
+                "--frames, --size, --software and --screenshot require --headless"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert_eq!(
+            parse_err(&["--headless", "--frames"]),
+            "--frames requires a value"
+        );
+        assert_eq!(
+            parse_err(&["--headless", "--frames", "many"]),
+            "invalid --frames value \"many\": invalid digit found in string"
+        );
+        assert_eq!(
+            parse_err(&["--capture-dir", "f", "--capture-every", "0"]),
+            "invalid --capture-every value \"0\""
+        );
+        assert_eq!(
+            parse_err(&["--capture-every", "2"]),
+            "--capture-every requires --capture-dir"
+        );
+        assert_eq!(
+            parse_err(&["--fullscreen"]),
+            "unknown argument \"--fullscreen\""
+        );
+    }
+
+    #[test]
+    fn output_selection() {
+        let options = parse(&[
+            "--output",
+            "DP-1",
+            "--output",
+            "LG TV",
+            "--exclude-output",
+            "eDP-1",
+        ])
+        .unwrap();
+        let mut outputs = OutputsConfig {
+            include: vec!["HDMI-A-1".to_string()],
+            exclude: vec!["DP-2".to_string()],
+            ..OutputsConfig::default()
+        };
+        options.outputs.apply(&mut outputs);
+        assert_eq!(outputs.include, ["DP-1", "LG TV"]);
+        assert_eq!(outputs.exclude, ["eDP-1"]);
+    }
+}
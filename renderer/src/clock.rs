@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+/// Length of one simulation step. The swarm advances in steps of exactly this length whatever
+/// the display's refresh rate, so it behaves the same at 60 Hz, 144 Hz or under a frame cap.
+pub const SIM_STEP: Duration = Duration::from_nanos(16_666_667);
+/// Most steps run to catch up in one frame; time beyond that is dropped, slowing the swarm
+/// down rather than stalling the frame.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// Animation time that stands still while the renderer is suspended, so the entity and the
+/// swarm pick up exactly where they left off instead of jumping ahead.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    start: Instant,
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            paused_at: None,
+            paused_total: Duration::ZERO,
+        }
+    }
+
+    /// The current animation instant: wall time minus the time spent paused.
+    pub fn now(&self) -> Instant {
+        self.paused_at.unwrap_or_else(Instant::now) - self.paused_total
+    }
+
+    /// Animation time of `instant` (from [`Clock::now`]) in seconds.
+    pub fn secs_at(&self, instant: Instant) -> f32 {
+        instant.duration_since(self.start).as_secs_f32()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(Instant::now);
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_total += paused_at.elapsed();
+        }
+    }
+}
+
+/// Fixed-timestep accumulator: turns the animation time that passed between frames into a
+/// whole number of [`SIM_STEP`]s.
+#[derive(Debug, Clone, Default)]
+pub struct FixedStep {
+    next: Option<Instant>,
+}
+
+impl FixedStep {
+    /// The animation instants of the steps due by `now`, oldest first. The first call yields a
+    /// single step at `now`.
+    pub fn due(&mut self, now: Instant) -> Vec<Instant> {
+        let mut next = self.next.unwrap_or(now);
+        let mut steps = Vec::new();
+        while next <= now {
+            if steps.len() as u32 == MAX_STEPS_PER_FRAME {
+                next = now + SIM_STEP;
+                break;
+            }
+            steps.push(next);
+            next += SIM_STEP;
+        }
+        self.next = Some(next);
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn first_call_yields_one_step() {
+        let mut steps = FixedStep::default();
+        let start = Instant::now();
+        assert_eq!(steps.due(start), vec![start]);
+        assert!(steps.due(start).is_empty());
+    }
+
+    #[test]
+    fn leftover_time_carries_over() {
+        let mut steps = FixedStep::default();
+        let start = Instant::now();
+        steps.due(start);
+        // Half a step short of two: one step now, and the half carries into the next frame.
+        assert_eq!(steps.due(start + SIM_STEP * 3 / 2), vec![start + SIM_STEP]);
+        assert_eq!(steps.due(start + SIM_STEP * 2), vec![start + SIM_STEP * 2]);
+        assert_eq!(
+            steps.due(start + SIM_STEP * 4),
+            vec![start + SIM_STEP * 3, start + SIM_STEP * 4]
+        );
+    }
+
+    #[test]
+    fn long_stall_is_clamped() {
+        let mut steps = FixedStep::default();
+        let start = Instant::now();
+        steps.due(start);
+        let after_stall = start + Duration::from_secs(5);
+        let due = steps.due(after_stall);
+        assert_eq!(due.len(), MAX_STEPS_PER_FRAME as usize);
+        assert_eq!(due[0], start + SIM_STEP);
+        // The rest of the stall is dropped rather than run over the following frames.
+        assert!(steps.due(after_stall).is_empty());
+        assert_eq!(
+            steps.due(after_stall + SIM_STEP),
+            vec![after_stall + SIM_STEP]
+        );
+    }
+
+    #[test]
+    fn paused_clock_stands_still() {
+        let mut clock = Clock::new();
+        let mut steps = FixedStep::default();
+        steps.due(clock.now());
+        clock.pause();
+        assert!(clock.is_paused());
+        let paused_at = clock.now();
+        thread::sleep(SIM_STEP * 3);
+        assert_eq!(clock.now(), paused_at);
+        assert!(steps.due(clock.now()).is_empty());
+
+        clock.resume();
+        assert!(!clock.is_paused());
+        // The time spent paused is skipped, not caught up on.
+        assert!(clock.now().duration_since(paused_at) < SIM_STEP * 2);
+        assert!(steps.due(clock.now()).len() <= 2);
+    }
+}
@@ -0,0 +1,683 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{Palette, Presets, StatePreset},
+    gpu::{Backend, MAX_FLOCKING_PARTICLES},
+};
+
+pub const STATE_NAMES: [&str; 6] = ["idle", "curious", "focused", "amused", "alert", "sleepy"];
+
+const DEFAULT_TRANSITION_DURATION: Duration = Duration::from_millis(750);
+const DEFAULT_GAZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether all outputs show one swarm spread across the desktop, or each output runs its own.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SwarmMode {
+    #[default]
+    Shared,
+    PerOutput,
+}
+
+/// The `[outputs]` table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputsConfig {
+    pub swarm: SwarmMode,
+    /// Outputs to draw on; empty means all of them.
+    pub include: Vec<String>,
+    /// Outputs never to draw on, even if `include` matches them.
+    pub exclude: Vec<String>,
+    /// Fraction of the native pixel resolution to render at; the compositor scales the
+    /// result up. Needs `wp_viewporter`.
+    pub render_scale: f32,
+}
+
+impl Default for OutputsConfig {
+    fn default() -> Self {
+        Self {
+            swarm: SwarmMode::default(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            render_scale: 1.0,
+        }
+    }
+}
+
+impl OutputsConfig {
+    /// Whether an output gets a wallpaper. A pattern matches an output whose name equals it
+    /// (`DP-1`) or whose description contains it (`"LG TV"`).
+    pub fn selects(&self, name: Option<&str>, description: Option<&str>) -> bool {
+        let matches = |pattern: &String| {
+            name == Some(pattern.as_str())
+                || description.is_some_and(|description| description.contains(pattern.as_str()))
+        };
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// The `[particles]` table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParticlesConfig {
+    /// How particles are simulated and drawn; read once at startup.
+    pub backend: Backend,
+    /// Particles per swarm.
+    pub count: u32,
+    /// Points drawn along each particle's velocity per frame, for its trail.
+    pub trail_steps: u32,
+    /// Adds the states' boids forces between neighboring particles.
+    pub flocking: bool,
+}
+
+impl Default for ParticlesConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            count: 70,
+            trail_steps: 4,
+            flocking: false,
+        }
+    }
+}
+
+/// When the `[power]` low-power profile is used.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerMode {
+    /// While the machine runs on battery.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The `[power]` table: the low-power profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    pub mode: PowerMode,
+    /// Frame rate ceiling while in low-power mode.
+    pub max_fps: f32,
+    /// Caps `outputs.render_scale` while in low-power mode.
+    pub render_scale: f32,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            mode: PowerMode::default(),
+            max_fps: 30.0,
+            render_scale: 0.5,
+        }
+    }
+}
+
+/// Renderer settings from `renderer.toml`. Every key is optional; missing keys keep the
+/// built-in defaults, and a state preset only needs the fields that differ from that state's
+/// defaults.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub transition_duration: Duration,
+    pub gaze_timeout: Duration,
+    pub socket_path: Option<PathBuf>,
+    /// Where to listen for control clients; unset leaves the control socket off.
+    pub control_socket: Option<PathBuf>,
+    /// Custom WGSL for the present pass; relative paths are resolved against the config
+    /// file's directory.
+    pub present_shader: Option<PathBuf>,
+    /// Frame rate ceiling per output; 0 follows the display's refresh rate.
+    pub max_fps: f32,
+    pub particles: ParticlesConfig,
+    pub outputs: OutputsConfig,
+    pub power: PowerConfig,
+    pub presets: Presets,
+    pub palette: Palette,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            transition_duration: DEFAULT_TRANSITION_DURATION,
+            gaze_timeout: DEFAULT_GAZE_TIMEOUT,
+            socket_path: None,
+            control_socket: None,
+            present_shader: None,
+            max_fps: 0.0,
+            particles: ParticlesConfig::default(),
+            outputs: OutputsConfig::default(),
+            power: PowerConfig::default(),
+            presets: Presets::default(),
+            palette: Palette::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    transition_duration: Option<f32>,
+    gaze_timeout: Option<f32>,
+    socket_path: Option<PathBuf>,
+    control_socket: Option<PathBuf>,
+    present_shader: Option<PathBuf>,
+    max_fps: Option<f32>,
+    #[serde(default)]
+    particles: ParticlesConfig,
+    #[serde(default)]
+    outputs: OutputsConfig,
+    #[serde(default)]
+    power: PowerConfig,
+    palette: Option<toml::Value>,
+    #[serde(default)]
+    states: toml::Table,
+}
+
+/// `$XDG_CONFIG_HOME/sentinel/renderer.toml`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(base.join("sentinel").join("renderer.toml"))
+}
+
+/// The config file to use: `explicit` (from `--config`), then `SENTINEL_CONFIG`, then the
+/// XDG default. The boolean is true when the user named the file, so it must exist.
+pub fn resolve_path(explicit: Option<&Path>) -> Option<(PathBuf, bool)> {
+    if let Some(path) = explicit {
+        return Some((path.to_path_buf(), true));
+    }
+    if let Some(path) = env::var_os("SENTINEL_CONFIG").filter(|p| !p.is_empty()) {
+        return Some((PathBuf::from(path), true));
+    }
+    default_path().map(|path| (path, false))
+}
+
+impl Config {
+    /// Loads the config file (see [`resolve_path`]) and applies environment overrides.
+    pub fn load(explicit: Option<&Path>) -> anyhow::Result<Self> {
+        let mut config = match resolve_path(explicit) {
+            Some((path, required)) => match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    let mut config = Self::parse(&text)
+                        .with_context(|| format!("invalid config file {}", path.display()))?;
+                    if let (Some(shader), Some(dir)) = (&mut config.present_shader, path.parent()) {
+                        *shader = dir.join(&*shader);
+                    }
+                    info!("Loaded config from {}", path.display());
+                    config
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => {
+                    Self::default()
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("failed to read config file {}", path.display()))
+                }
+            },
+            None => Self::default(),
+        };
+        config.apply_env_overrides();
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let raw: RawConfig = toml::from_str(text)?;
+        let mut config = Self::default();
+
+        if let Some(seconds) = raw.transition_duration {
+            config.transition_duration = seconds_in_range("transition_duration", seconds, 60.0)?;
+        }
+        if let Some(seconds) = raw.gaze_timeout {
+            config.gaze_timeout = seconds_in_range("gaze_timeout", seconds, 3600.0)?;
+        }
+        config.socket_path = raw.socket_path;
+        config.control_socket = raw.control_socket;
+        config.present_shader = raw.present_shader;
+        if let Some(max_fps) = raw.max_fps {
+            if !max_fps.is_finite() || !(0.0..=1000.0).contains(&max_fps) {
+                bail!("`max_fps` must be between 0 and 1000, got {max_fps}");
+            }
+            config.max_fps = max_fps;
+        }
+        let mut particles = raw.particles;
+        let max_particles = particles.backend.max_particles();
+        if !(1..=max_particles).contains(&particles.count) {
+            bail!(
+                "`particles.count` must be between 1 and {max_particles} with the {:?} backend, \
+                 got {}",
+                particles.backend,
+                particles.count
+            );
+        }
+        if !(1..=16).contains(&particles.trail_steps) {
+            bail!(
+                "`particles.trail_steps` must be between 1 and 16, got {}",
+                particles.trail_steps
+            );
+        }
+        if particles.flocking
+            && particles.backend == Backend::Compute
+            && particles.count > MAX_FLOCKING_PARTICLES
+        {
+            warn!(
+                "`particles.count` {} is more than the flocking grid holds; capping it at \
+                 {MAX_FLOCKING_PARTICLES}",
+                particles.count
+            );
+            particles.count = MAX_FLOCKING_PARTICLES;
+        }
+        config.particles = particles;
+        check(
+            "outputs",
+            "render_scale",
+            raw.outputs.render_scale,
+            0.25,
+            1.0,
+        )?;
+        config.outputs = raw.outputs;
+        check("power", "max_fps", raw.power.max_fps, 1.0, 1000.0)?;
+        check("power", "render_scale", raw.power.render_scale, 0.25, 1.0)?;
+        config.power = raw.power;
+        if let Some(value) = raw.palette {
+            config.palette = parse_palette(value)?;
+        }
+
+        for (name, value) in raw.states {
+            let key = format!("states.{name}");
+            let state = STATE_NAMES.iter().position(|n| *n == name).ok_or_else(|| {
+                anyhow!(
+                    "unknown state `{key}` (expected one of {})",
+                    STATE_NAMES.join(", ")
+                )
+            })?;
+            config.presets.0[state] = parse_preset(state as u32, value, &key)?;
+        }
+
+        Ok(config)
+    }
+
+    fn apply_env_overrides(&mut self) {
+        if let Some(duration) = env_seconds("SENTINEL_TRANSITION_DURATION") {
+            self.transition_duration = duration;
+        }
+        if let Some(duration) = env_seconds("SENTINEL_GAZE_TIMEOUT") {
+            self.gaze_timeout = duration;
+        }
+    }
+
+    /// The built-in defaults as a complete TOML document, for `--print-default-config`.
+    pub fn default_toml() -> String {
+        #[derive(Serialize)]
+        struct Document {
+            transition_duration: f32,
+            gaze_timeout: f32,
+            max_fps: f32,
+            particles: ParticlesConfig,
+            outputs: OutputsConfig,
+            power: PowerConfig,
+            palette: toml::Value,
+            states: toml::Table,
+        }
+
+        let defaults = Self::default();
+        let states = STATE_NAMES
+            .iter()
+            .zip(defaults.presets.0.iter())
+            .map(|(name, preset)| {
+                let mut value = toml::Value::try_from(preset).expect("presets serialize to TOML");
+                tidy_floats(&mut value);
+                (name.to_string(), value)
+            })
+            .collect();
+        let mut palette = toml::Value::try_from(defaults.palette).expect("palette serializes");
+        tidy_floats(&mut palette);
+        toml::to_string(&Document {
+            transition_duration: defaults.transition_duration.as_secs_f32(),
+            gaze_timeout: defaults.gaze_timeout.as_secs_f32(),
+            max_fps: defaults.max_fps,
+            particles: defaults.particles,
+            outputs: defaults.outputs,
+            power: defaults.power,
+            palette,
+            states,
+        })
+        .expect("default config serializes to TOML")
+    }
+}
+
+/// Re-rounds values that went through `f32` so they print as written (0.7, not
+/// 0.699999988079071).
+fn tidy_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(f) => *f = (*f as f32).to_string().parse().unwrap_or(*f),
+        toml::Value::Array(items) => items.iter_mut().for_each(tidy_floats),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, v)| tidy_floats(v)),
+        _ => {}
+    }
+}
+
+fn parse_preset(state: u32, value: toml::Value, key: &str) -> anyhow::Result<StatePreset> {
+    let mut merged =
+        toml::Value::try_from(StatePreset::defaults(state)).expect("presets serialize to TOML");
+    merge(&mut merged, value, key)?;
+    let preset = StatePreset::deserialize(merged).with_context(|| format!("invalid `{key}`"))?;
+    validate_preset(&preset, key)?;
+    Ok(preset)
+}
+
+fn parse_palette(value: toml::Value) -> anyhow::Result<Palette> {
+    let mut merged = toml::Value::try_from(Palette::default()).expect("palette serializes");
+    merge(&mut merged, value, "palette")?;
+    let palette = Palette::deserialize(merged).context("invalid `palette`")?;
+    for (index, frequency) in palette.frequency.iter().enumerate() {
+        check(
+            "palette",
+            &format!("frequency[{index}]"),
+            *frequency,
+            0.0,
+            20.0,
+        )?;
+    }
+    for (index, phase) in palette.phase.iter().enumerate() {
+        check("palette", &format!("phase[{index}]"), *phase, -10.0, 10.0)?;
+    }
+    check("palette", "amplitude", palette.amplitude, 0.0, 2.0)?;
+    check("palette", "offset", palette.offset, -1.0, 2.0)?;
+    Ok(palette)
+}
+
+/// Overlays `overlay` onto `base`, rejecting keys and value types that `base` doesn't have.
+fn merge(base: &mut toml::Value, overlay: toml::Value, key: &str) -> anyhow::Result<()> {
+    use toml::Value;
+
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (name, value) in overlay {
+                let child_key = format!("{key}.{name}");
+                let Some(slot) = base.get_mut(&name) else {
+                    let expected = base.keys().cloned().collect::<Vec<_>>().join(", ");
+                    bail!("unknown key `{child_key}` (expected one of {expected})");
+                };
+                merge(slot, value, &child_key)?;
+            }
+        }
+        (Value::Table(_), other) => {
+            bail!("`{key}` must be a table, got {}", other.type_str())
+        }
+        (Value::Array(base), Value::Array(overlay)) => {
+            if base.len() != overlay.len() {
+                bail!(
+                    "`{key}` must have {} elements, got {}",
+                    base.len(),
+                    overlay.len()
+                );
+            }
+            for (index, (slot, value)) in base.iter_mut().zip(overlay).enumerate() {
+                merge(slot, value, &format!("{key}[{index}]"))?;
+            }
+        }
+        (Value::Float(slot), Value::Float(value)) => *slot = value,
+        (Value::Float(slot), Value::Integer(value)) => *slot = value as f64,
+        (base, other) => {
+            bail!(
+                "`{key}` must be {}, got {}",
+                expected_type(base),
+                other.type_str()
+            )
+        }
+    }
+    Ok(())
+}
+
+fn expected_type(value: &toml::Value) -> &'static str {
+    match value {
+        toml::Value::Float(_) | toml::Value::Integer(_) => "a number",
+        toml::Value::Array(_) => "an array",
+        toml::Value::Table(_) => "a table",
+        toml::Value::Boolean(_) => "a boolean",
+        toml::Value::String(_) => "a string",
+        toml::Value::Datetime(_) => "a datetime",
+    }
+}
+
+fn validate_preset(preset: &StatePreset, key: &str) -> anyhow::Result<()> {
+    let m = &preset.motion;
+    let motion = format!("{key}.motion");
+    check(&motion, "base_scale", m.base_scale, 0.35, 2.5)?;
+    check(&motion, "scale_pulse", m.scale_pulse, 0.0, 1.0)?;
+    check(&motion, "pulse_speed", m.pulse_speed, 0.0, 10.0)?;
+    check(&motion, "drift_amp[0]", m.drift_amp[0], 0.0, 0.5)?;
+    check(&motion, "drift_amp[1]", m.drift_amp[1], 0.0, 0.5)?;
+    check(&motion, "drift_speed", m.drift_speed, 0.0, 10.0)?;
+    check(&motion, "bounce_mix", m.bounce_mix, 0.0, 1.0)?;
+    check(&motion, "bounce_speed", m.bounce_speed, 0.0, 10.0)?;
+    check(&motion, "base_offset[0]", m.base_offset[0], -0.45, 0.45)?;
+    check(&motion, "base_offset[1]", m.base_offset[1], -0.45, 0.45)?;
+    check(&motion, "smooth_time", m.smooth_time, 0.05, 10.0)?;
+    check(&motion, "gaze_mix", m.gaze_mix, 0.0, 1.0)?;
+
+    // Same bounds the uniforms are clamped to before upload.
+    let s = &preset.synaptic;
+    let synaptic = format!("{key}.synaptic");
+    check(&synaptic, "damping", s.damping, 0.95, 0.99999)?;
+    check(&synaptic, "noise_strength", s.noise_strength, 0.0, 25.0)?;
+    check(&synaptic, "attraction", s.attraction, 0.0, 2.0)?;
+    check(&synaptic, "speed", s.speed, 0.0, 4.0)?;
+    check(&synaptic, "trail_fade", s.trail_fade, 0.9, 0.99999)?;
+    check(&synaptic, "glow_intensity", s.glow_intensity, 0.0, 4.0)?;
+    check(&synaptic, "color_shift", s.color_shift, -1.0, 1.0)?;
+
+    let w = &preset.swarm;
+    let swarm = format!("{key}.swarm");
+    check(&swarm, "center_pull", w.center_pull, -2.0, 4.0)?;
+    check(&swarm, "noise_scale", w.noise_scale, 0.0, 4.0)?;
+    check(&swarm, "goal_pull", w.goal_pull, 0.0, 4.0)?;
+    check(&swarm, "damping_scale", w.damping_scale, 0.5, 1.0)?;
+    check(&swarm, "force_gain", w.force_gain, 0.0, 4.0)?;
+
+    // Radii are bounded so the compute backend's neighbor grid stays small.
+    let f = &preset.flock;
+    let flock = format!("{key}.flock");
+    check(&flock, "separation", f.separation, 0.0, 4.0)?;
+    check(&flock, "separation_radius", f.separation_radius, 0.02, 0.5)?;
+    check(&flock, "alignment", f.alignment, 0.0, 4.0)?;
+    check(&flock, "alignment_radius", f.alignment_radius, 0.02, 0.5)?;
+    check(&flock, "cohesion", f.cohesion, 0.0, 4.0)?;
+    check(&flock, "cohesion_radius", f.cohesion_radius, 0.02, 0.5)?;
+    Ok(())
+}
+
+fn check(section: &str, name: &str, value: f32, min: f32, max: f32) -> anyhow::Result<()> {
+    if !value.is_finite() || value < min || value > max {
+        bail!("`{section}.{name}` must be between {min} and {max}, got {value}");
+    }
+    Ok(())
+}
+
+fn seconds_in_range(key: &str, seconds: f32, max: f32) -> anyhow::Result<Duration> {
+    if !seconds.is_finite() || seconds <= 0.0 || seconds > max {
+        bail!("`{key}` must be greater than 0 and at most {max} seconds, got {seconds}");
+    }
+    Ok(Duration::from_secs_f32(seconds))
+}
+
+fn env_seconds(name: &str) -> Option<Duration> {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<f32>().ok())
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .map(Duration::from_secs_f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::EntityState;
+
+    fn parse_err(text: &str) -> String {
+        format!(
+            "{:#}",
+            Config::parse(text).expect_err("config should be rejected")
+        )
+    }
+
+    fn preset_toml(preset: &StatePreset) -> toml::Value {
+        toml::Value::try_from(preset).unwrap()
+    }
+
+    #[test]
+    fn empty_config_is_the_default() {
+        let config = Config::parse("").unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.transition_duration, defaults.transition_duration);
+        assert_eq!(config.particles, defaults.particles);
+        assert_eq!(config.outputs, defaults.outputs);
+        assert_eq!(config.palette, defaults.palette);
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        assert_eq!(
+            parse_err("[states.alert.motion]\nbase_scale = 9"),
+            "`states.alert.motion.base_scale` must be between 0.35 and 2.5, got 9"
+        );
+        assert!(parse_err("[states.idle.motion]\nwobble = 1")
+            .starts_with("unknown key `states.idle.motion.wobble`"));
+        assert_eq!(
+            parse_err("[states.idle.motion]\ndrift_amp = [0.1]"),
+            "`states.idle.motion.drift_amp` must have 2 elements, got 1"
+        );
+        assert_eq!(
+            parse_err("[states.idle.synaptic]\nspeed = \"fast\""),
+            "`states.idle.synaptic.speed` must be a number, got string"
+        );
+        assert!(parse_err("[states.grumpy]").starts_with("unknown state `states.grumpy`"));
+        assert_eq!(
+            parse_err("[palette]\nphase = [0, 0, 11]"),
+            "`palette.phase[2]` must be between -10 and 10, got 11"
+        );
+        assert_eq!(
+            parse_err("[power]\nrender_scale = 2"),
+            "`power.render_scale` must be between 0.25 and 1, got 2"
+        );
+        assert_eq!(
+            parse_err("transition_duration = 0"),
+            "`transition_duration` must be greater than 0 and at most 60 seconds, got 0"
+        );
+    }
+
+    #[test]
+    fn presets_merge_over_the_defaults() {
+        let config = Config::parse("[states.alert.motion]\nbase_scale = 1.5").unwrap();
+        let alert = EntityState::Alert.as_u32() as usize;
+        let mut expected = StatePreset::defaults(alert as u32);
+        expected.motion.base_scale = 1.5;
+        assert_eq!(
+            preset_toml(&config.presets.0[alert]),
+            preset_toml(&expected)
+        );
+        for (state, preset) in config.presets.0.iter().enumerate() {
+            if state != alert {
+                assert_eq!(
+                    preset_toml(preset),
+                    preset_toml(&StatePreset::defaults(state as u32))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn particle_limits_per_backend() {
+        let count = |backend: &str, count: u32| {
+            Config::parse(&format!(
+                "[particles]\nbackend = \"{backend}\"\ncount = {count}"
+            ))
+        };
+        assert!(count("fragment", 2048).is_ok());
+        assert_eq!(
+            format!("{:#}", count("fragment", 2049).unwrap_err()),
+            "`particles.count` must be between 1 and 2048 with the Fragment backend, got 2049"
+        );
+        assert!(count("compute", 65536).is_ok());
+        assert!(count("compute", 65537).is_err());
+        assert!(count("compute", 0).is_err());
+    }
+
+    #[test]
+    fn flocking_caps_compute_swarms() {
+        let count = |backend: &str, flocking: bool| {
+            Config::parse(&format!(
+                "[particles]\nbackend = \"{backend}\"\ncount = 65536\nflocking = {flocking}"
+            ))
+            .map(|config| config.particles.count)
+        };
+        assert_eq!(count("compute", true).unwrap(), MAX_FLOCKING_PARTICLES);
+        assert_eq!(count("compute", false).unwrap(), 65536);
+        assert!(count("fragment", true).is_err());
+    }
+
+    #[test]
+    fn selects_by_name_or_description() {
+        let outputs = |include: &[&str], exclude: &[&str]| OutputsConfig {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            ..OutputsConfig::default()
+        };
+        let tv = (Some("HDMI-A-1"), Some("LG Electronics LG TV 0x01010101"));
+        let laptop = (Some("eDP-1"), Some("BOE 0x0BCA"));
+
+        let all = outputs(&[], &[]);
+        assert!(all.selects(tv.0, tv.1));
+        assert!(all.selects(None, None));
+
+        let by_name = outputs(&["eDP-1"], &[]);
+        assert!(by_name.selects(laptop.0, laptop.1));
+        assert!(!by_name.selects(tv.0, tv.1));
+        // Names have to match exactly.
+        assert!(!outputs(&["eDP"], &[]).selects(laptop.0, laptop.1));
+
+        let by_description = outputs(&["LG TV"], &[]);
+        assert!(by_description.selects(tv.0, tv.1));
+        assert!(!by_description.selects(laptop.0, laptop.1));
+        assert!(!by_description.selects(None, None));
+
+        let excluded = outputs(&[], &["LG TV"]);
+        assert!(!excluded.selects(tv.0, tv.1));
+        assert!(excluded.selects(laptop.0, laptop.1));
+        assert!(!outputs(&["HDMI-A-1"], &["HDMI-A-1"]).selects(tv.0, tv.1));
+    }
+
+    #[test]
+    fn default_toml_round_trips() {
+        let config = Config::parse(&Config::default_toml()).unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.transition_duration, defaults.transition_duration);
+        assert_eq!(config.gaze_timeout, defaults.gaze_timeout);
+        assert_eq!(config.max_fps, defaults.max_fps);
+        assert_eq!(config.particles, defaults.particles);
+        assert_eq!(config.outputs, defaults.outputs);
+        assert_eq!(config.power, defaults.power);
+        assert_eq!(
+            toml::Value::try_from(config.palette).unwrap(),
+            toml::Value::try_from(defaults.palette).unwrap()
+        );
+        for (parsed, default) in config.presets.0.iter().zip(defaults.presets.0.iter()) {
+            assert_eq!(preset_toml(parsed), preset_toml(default));
+        }
+    }
+}
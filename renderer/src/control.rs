@@ -0,0 +1,460 @@
+use std::{
+    env, fs, io,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context};
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction, RegistrationToken};
+use log::{debug, warn};
+
+use crate::ipc::{EntityState, PeerId};
+
+/// Where the renderer listens for control clients: `SENTINEL_CONTROL_SOCKET`, then
+/// `control_socket` from the config. `None` leaves the control socket off.
+pub fn socket_path(configured: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = env::var_os("SENTINEL_CONTROL_SOCKET").filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    configured.map(Path::to_path_buf)
+}
+
+/// The renderer's own listening socket, which any number of clients speak the IPC protocol
+/// on at once. Removes its file when dropped.
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Binds `path`, replacing a socket file left behind by a renderer that is gone. Fails if
+    /// another process is still listening there or the path is not a socket.
+    pub fn bind(path: &Path) -> anyhow::Result<Self> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                bail!("{} exists and is not a socket", path.display());
+            }
+            if UnixStream::connect(path).is_ok() {
+                bail!("another process is listening on {}", path.display());
+            }
+            fs::remove_file(path)
+                .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to listen on {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Registers the socket with the event loop; `on_accept` gets each new client's stream,
+    /// already non-blocking.
+    pub fn register<D: 'static>(
+        self,
+        handle: &LoopHandle<'static, D>,
+        mut on_accept: impl FnMut(UnixStream, &mut D) + 'static,
+    ) -> anyhow::Result<RegistrationToken> {
+        handle
+            .insert_source(
+                Generic::new(self, Interest::READ, Mode::Level),
+                move |_, socket, data| {
+                    loop {
+                        match socket.listener.accept() {
+                            Ok((stream, _)) => match stream.set_nonblocking(true) {
+                                Ok(()) => on_accept(stream, data),
+                                Err(err) => warn!("Dropping control client: {err}"),
+                            },
+                            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                            Err(err) => {
+                                warn!("Control socket accept failed: {err}");
+                                break;
+                            }
+                        }
+                    }
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|err| anyhow::anyhow!("failed to register control socket: {err}"))
+    }
+}
+
+impl std::os::fd::AsFd for ControlSocket {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.path) {
+            Ok(()) => debug!("Removed control socket {}", self.path.display()),
+            Err(err) => debug!("Failed to remove {}: {err}", self.path.display()),
+        }
+    }
+}
+
+/// Most overrides kept at once; a new one beyond this pushes out the oldest.
+const MAX_OVERRIDES: usize = 8;
+
+/// A request for a state: a peer's latest `state` message, or one of its overrides.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Claim {
+    /// `None` for the state the renderer started in, or the last one a disconnected peer
+    /// left behind in its place.
+    pub peer: Option<PeerId>,
+    pub priority: i32,
+    pub state: EntityState,
+    pub intensity: f32,
+    /// When an override ends; `None` for a peer's standing claim.
+    pub expires: Option<Instant>,
+    /// A standing claim that stays, with its priority, after the peer disconnects.
+    pub persist: bool,
+    /// Order of arrival, for breaking ties between equal priorities.
+    seq: u64,
+}
+
+/// Arbitrates between the `state` messages of several peers. A `state` without a duration
+/// is the peer's standing claim, held until it sends another, sends `release` or disconnects;
+/// with `persist` it is left behind on disconnecting, keeping its priority and replacing the
+/// state the renderer started in (or the one left behind before). One
+/// with `duration_ms` is an override pushed on top, which ends after that time even if the
+/// peer has gone, and replaces the peer's previous override.
+///
+/// The claim with the highest priority is shown; among equal priorities an override before a
+/// standing claim, and otherwise the most recent one, so with every peer at the default
+/// priority the last writer wins. Underneath them all is the state the renderer started in,
+/// at the lowest priority, unless a peer left one behind.
+#[derive(Debug)]
+pub struct StateClaims {
+    claims: Vec<Claim>,
+    next_seq: u64,
+}
+
+impl StateClaims {
+    pub fn new(state: EntityState, intensity: f32) -> Self {
+        Self {
+            claims: vec![Claim {
+                peer: None,
+                priority: i32::MIN,
+                state,
+                intensity,
+                expires: None,
+                persist: true,
+                seq: 0,
+            }],
+            next_seq: 0,
+        }
+    }
+
+    fn winner(&self) -> Option<&Claim> {
+        self.claims
+            .iter()
+            .max_by_key(|claim| (claim.priority, claim.expires.is_some(), claim.seq))
+    }
+
+    /// Applies `change` to the claims. Returns the claim to show if it is a different one
+    /// than before.
+    fn update(&mut self, change: impl FnOnce(&mut Vec<Claim>)) -> Option<Claim> {
+        let shown = self.winner().map(|claim| claim.seq);
+        change(&mut self.claims);
+        self.winner()
+            .filter(|claim| Some(claim.seq) != shown)
+            .copied()
+    }
+
+    /// Replaces `peer`'s standing claim, or with `expires` its override. Like the other
+    /// changes, returns the claim to show if that is no longer the same one.
+    pub fn claim(
+        &mut self,
+        peer: PeerId,
+        priority: i32,
+        state: EntityState,
+        intensity: f32,
+        expires: Option<Instant>,
+        persist: bool,
+    ) -> Option<Claim> {
+        self.next_seq += 1;
+        let claim = Claim {
+            peer: Some(peer),
+            priority,
+            state,
+            intensity,
+            expires,
+            persist: persist && expires.is_none(),
+            seq: self.next_seq,
+        };
+        self.update(|claims| {
+            claims.retain(|c| c.peer != claim.peer || c.expires.is_some() != expires.is_some());
+            if expires.is_some()
+                && claims.iter().filter(|c| c.expires.is_some()).count() >= MAX_OVERRIDES
+            {
+                if let Some(oldest) = claims
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.expires.is_some())
+                    .min_by_key(|(_, c)| c.seq)
+                    .map(|(index, _)| index)
+                {
+                    claims.remove(oldest);
+                }
+            }
+            claims.push(claim);
+        })
+    }
+
+    /// Drops all of `peer`'s claims, for `release`. Returns the claim to show instead if one
+    /// of them was shown.
+    pub fn release(&mut self, peer: PeerId) -> Option<Claim> {
+        self.update(|claims| claims.retain(|claim| claim.peer != Some(peer)))
+    }
+
+    /// Drops `peer`'s standing claim once it has disconnected, or with `persist` leaves it
+    /// behind, at the same priority, in place of the previous base claim; its override runs
+    /// its course.
+    pub fn disconnect(&mut self, peer: PeerId) -> Option<Claim> {
+        self.update(|claims| {
+            let left = claims
+                .iter()
+                .position(|claim| claim.peer == Some(peer) && claim.persist)
+                .map(|index| claims.remove(index));
+            claims.retain(|claim| claim.peer != Some(peer) || claim.expires.is_some());
+            if let Some(left) = left {
+                claims.retain(|claim| claim.peer.is_some());
+                claims.push(Claim { peer: None, ..left });
+            }
+        })
+    }
+
+    /// When the next override ends, if any is running.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.claims.iter().filter_map(|claim| claim.expires).min()
+    }
+
+    /// Drops the overrides that have ended by `now`.
+    pub fn expire(&mut self, now: Instant) -> Option<Claim> {
+        self.update(|claims| {
+            claims.retain(|claim| claim.expires.is_none_or(|expires| expires > now))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use EntityState::*;
+
+    fn shown(claims: &StateClaims) -> EntityState {
+        claims
+            .winner()
+            .expect("the baseline claim is never dropped")
+            .state
+    }
+
+    fn changed_to(change: Option<Claim>) -> Option<EntityState> {
+        change.map(|claim| claim.state)
+    }
+
+    #[test]
+    fn higher_priority_wins() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        assert_eq!(
+            changed_to(claims.claim(1, 5, Alert, 1.0, None, false)),
+            Some(Alert)
+        );
+        assert_eq!(
+            changed_to(claims.claim(2, 0, Sleepy, 1.0, None, false)),
+            None
+        );
+        assert_eq!(shown(&claims), Alert);
+        assert_eq!(
+            changed_to(claims.claim(3, 6, Amused, 1.0, None, false)),
+            Some(Amused)
+        );
+    }
+
+    #[test]
+    fn override_beats_standing_claim_at_equal_priority() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        let expires = Instant::now() + Duration::from_secs(10);
+        claims.claim(1, 0, Alert, 1.0, Some(expires), false);
+        assert_eq!(
+            changed_to(claims.claim(2, 0, Focused, 1.0, None, false)),
+            None
+        );
+        assert_eq!(shown(&claims), Alert);
+    }
+
+    #[test]
+    fn last_writer_wins_ties() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        claims.claim(1, 0, Curious, 1.0, None, false);
+        assert_eq!(
+            changed_to(claims.claim(2, 0, Focused, 1.0, None, false)),
+            Some(Focused)
+        );
+        assert_eq!(
+            changed_to(claims.claim(1, 0, Amused, 1.0, None, false)),
+            Some(Amused)
+        );
+    }
+
+    #[test]
+    fn update_reports_only_a_new_winner() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        claims.claim(1, 5, Alert, 1.0, None, false);
+        // Replacing the shown claim makes it a different one, even with the same state.
+        assert_eq!(
+            changed_to(claims.claim(1, 5, Alert, 0.2, None, false)),
+            Some(Alert)
+        );
+        // Changes underneath it do not.
+        assert_eq!(
+            changed_to(claims.claim(2, 0, Sleepy, 1.0, None, false)),
+            None
+        );
+        assert_eq!(changed_to(claims.release(2)), None);
+        assert_eq!(changed_to(claims.expire(Instant::now())), None);
+    }
+
+    #[test]
+    fn max_overrides_evicts_the_oldest() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        let now = Instant::now();
+        for peer in 0..MAX_OVERRIDES as PeerId {
+            claims.claim(
+                peer,
+                0,
+                Curious,
+                1.0,
+                Some(now + Duration::from_secs(60)),
+                false,
+            );
+        }
+        assert!(claims.claims.iter().any(|claim| claim.peer == Some(0)));
+        claims.claim(
+            100,
+            0,
+            Alert,
+            1.0,
+            Some(now + Duration::from_secs(60)),
+            false,
+        );
+        let overrides: Vec<_> = claims
+            .claims
+            .iter()
+            .filter(|claim| claim.expires.is_some())
+            .collect();
+        assert_eq!(overrides.len(), MAX_OVERRIDES);
+        assert!(overrides.iter().all(|claim| claim.peer != Some(0)));
+        assert_eq!(shown(&claims), Alert);
+    }
+
+    #[test]
+    fn new_override_replaces_the_peers_previous_one() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        let now = Instant::now();
+        claims.claim(1, 0, Alert, 1.0, Some(now + Duration::from_secs(60)), false);
+        claims.claim(1, 0, Amused, 1.0, Some(now + Duration::from_secs(5)), false);
+        assert_eq!(claims.next_expiry(), Some(now + Duration::from_secs(5)));
+        assert_eq!(claims.claims.len(), 2);
+    }
+
+    #[test]
+    fn disconnect_keeps_overrides() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        let expires = Instant::now() + Duration::from_secs(10);
+        claims.claim(1, 0, Focused, 1.0, None, false);
+        claims.claim(1, 0, Alert, 1.0, Some(expires), false);
+        assert_eq!(changed_to(claims.disconnect(1)), None);
+        assert_eq!(shown(&claims), Alert);
+        assert_eq!(changed_to(claims.expire(expires)), Some(Idle));
+    }
+
+    #[test]
+    fn disconnect_leaves_a_persisted_claim_behind() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        claims.claim(1, 3, Sleepy, 0.4, None, true);
+        assert_eq!(changed_to(claims.disconnect(1)), None);
+        assert_eq!(shown(&claims), Sleepy);
+        // It replaced the starting state.
+        assert_eq!(claims.claims.len(), 1);
+        assert_eq!(
+            changed_to(claims.claim(2, 5, Curious, 1.0, None, false)),
+            Some(Curious)
+        );
+        assert_eq!(changed_to(claims.release(2)), Some(Sleepy));
+    }
+
+    #[test]
+    fn persisted_claim_keeps_its_priority() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        let observer = 1;
+        claims.claim(observer, 0, Focused, 1.0, None, false);
+        claims.claim(2, 5, Alert, 1.0, None, true);
+        assert_eq!(changed_to(claims.disconnect(2)), None);
+        assert_eq!(shown(&claims), Alert);
+        // The observer's later updates still don't displace it.
+        assert_eq!(
+            changed_to(claims.claim(observer, 0, Amused, 1.0, None, false)),
+            None
+        );
+        assert_eq!(shown(&claims), Alert);
+        // An override at its priority still goes on top, as it would have before.
+        let expires = Instant::now() + Duration::from_secs(10);
+        assert_eq!(
+            changed_to(claims.claim(3, 5, Curious, 1.0, Some(expires), false)),
+            Some(Curious)
+        );
+        assert_eq!(changed_to(claims.expire(expires)), Some(Alert));
+        // A later persisted claim replaces it, at its own priority.
+        claims.claim(4, -1, Sleepy, 1.0, None, true);
+        assert_eq!(changed_to(claims.disconnect(4)), Some(Amused));
+    }
+
+    #[test]
+    fn release_drops_both_claims() {
+        let mut claims = StateClaims::new(Idle, 0.5);
+        claims.claim(1, 0, Focused, 1.0, None, true);
+        claims.claim(
+            1,
+            0,
+            Alert,
+            1.0,
+            Some(Instant::now() + Duration::from_secs(10)),
+            false,
+        );
+        assert_eq!(changed_to(claims.release(1)), Some(Idle));
+        assert_eq!(claims.claims.len(), 1);
+        assert_eq!(claims.next_expiry(), None);
+    }
+
+    #[test]
+    fn expire_falls_back_to_the_base_claim() {
+        let mut claims = StateClaims::new(Focused, 0.7);
+        let now = Instant::now();
+        claims.claim(1, 0, Alert, 1.0, Some(now + Duration::from_secs(1)), false);
+        claims.claim(2, 0, Amused, 1.0, Some(now + Duration::from_secs(2)), false);
+        assert_eq!(claims.next_expiry(), Some(now + Duration::from_secs(1)));
+        assert_eq!(changed_to(claims.expire(now)), None);
+        assert_eq!(
+            changed_to(claims.expire(now + Duration::from_secs(1))),
+            None
+        );
+        assert_eq!(shown(&claims), Amused);
+        let base = claims.expire(now + Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            (base.peer, base.state, base.intensity),
+            (None, Focused, 0.7)
+        );
+        assert_eq!(claims.next_expiry(), None);
+    }
+}
@@ -0,0 +1,652 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde_json::{json, Value};
+
+use crate::{
+    config::{self, STATE_NAMES},
+    control, ipc,
+};
+
+pub const USAGE: &str = "\
+Usage: sentinel-renderer ctl [OPTIONS] <COMMAND>
+
+Drives a running renderer through its control socket.
+
+Commands:
+  state <STATE>       Show STATE (idle, curious, focused, amused, alert or sleepy)
+    --intensity <X>   How strongly, from 0 to 1 [default: 1]
+    --priority <N>    Priority over other clients' states [default: 0]
+    --for <DURATION>  Show it this long (e.g. 10s, 500ms, 2m), then return to the
+                      previous state; without it, until a newer state at the same
+                      or a higher priority replaces it
+    --hold            Show it until this command is interrupted, then release it
+  pulse               Swell the swarm once
+    --strength <X>    From 0 to 1 [default: 1]
+  pause               Freeze the animation
+  resume              Unfreeze it
+  status              Print what the renderer is showing
+    --json            Print the raw `status` message
+  capture [NAME]      Write the next frame to the file NAME, which must not exist, in
+                      the renderer's capture directory [default: a timestamped name];
+                      taken before the present pass, so without the effects of a
+                      custom present shader
+
+Options:
+  --socket <PATH>     Control socket [default: SENTINEL_CONTROL_SOCKET, then
+                      `control_socket` from the config]
+  --config <PATH>     Config file to read `control_socket` from
+  -h, --help          Print this help";
+
+/// How long to wait for the renderer's replies.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+enum Command {
+    State {
+        state: String,
+        intensity: f64,
+        priority: i32,
+        duration: Option<Duration>,
+        hold: bool,
+    },
+    Pulse {
+        strength: f64,
+    },
+    Pause,
+    Resume,
+    Status {
+        json: bool,
+    },
+    Capture {
+        name: Option<String>,
+    },
+}
+
+impl Command {
+    /// The message type the renderer has to accept for this command, if it sends one.
+    fn message_type(&self) -> Option<&'static str> {
+        match self {
+            Command::State { .. } => Some("state"),
+            Command::Pulse { .. } => Some("pulse"),
+            Command::Pause => Some("pause"),
+            Command::Resume => Some("resume"),
+            Command::Status { .. } => None,
+            Command::Capture { .. } => Some("capture"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CtlOptions {
+    help: bool,
+    socket: Option<PathBuf>,
+    config: Option<PathBuf>,
+    command: Option<Command>,
+}
+
+impl CtlOptions {
+    fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut help = false;
+        let mut socket = None;
+        let mut config = None;
+        let mut positional = Vec::new();
+        let mut intensity = None;
+        let mut priority = None;
+        let mut duration = None;
+        let mut hold = false;
+        let mut strength = None;
+        let mut json = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => help = true,
+                "--socket" => socket = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--config" => config = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--intensity" => {
+                    intensity = Some(parse_unit(&next_value(&mut args, &arg)?, &arg)?);
+                }
+                "--strength" => {
+                    strength = Some(parse_unit(&next_value(&mut args, &arg)?, &arg)?);
+                }
+                "--priority" => {
+                    let value = next_value(&mut args, &arg)?;
+                    priority = Some(
+                        value
+                            .parse::<i32>()
+                            .with_context(|| format!("invalid --priority value {value:?}"))?,
+                    );
+                }
+                "--for" => duration = Some(parse_duration(&next_value(&mut args, &arg)?)?),
+                "--hold" => hold = true,
+                "--json" => json = true,
+                other if other.starts_with('-') => bail!("unknown argument {other:?}"),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let name = positional.next();
+        let command = match name.as_deref() {
+            None => None,
+            Some("state") => {
+                let state = positional
+                    .next()
+                    .ok_or_else(|| anyhow!("`state` requires a state name"))?;
+                if !STATE_NAMES.contains(&state.as_str()) {
+                    bail!(
+                        "unknown state {state:?} (expected one of {})",
+                        STATE_NAMES.join(", ")
+                    );
+                }
+                Some(Command::State {
+                    state,
+                    intensity: intensity.unwrap_or(1.0),
+                    priority: priority.unwrap_or(0),
+                    duration,
+                    hold,
+                })
+            }
+            Some("pulse") => Some(Command::Pulse {
+                strength: strength.unwrap_or(1.0),
+            }),
+            Some("pause") => Some(Command::Pause),
+            Some("resume") => Some(Command::Resume),
+            Some("status") => Some(Command::Status { json }),
+            Some("capture") => Some(Command::Capture {
+                name: positional.next(),
+            }),
+            Some(other) => bail!("unknown command {other:?}"),
+        };
+        if let Some(extra) = positional.next() {
+            bail!("unexpected argument {extra:?}");
+        }
+
+        let used = |flag: &str, set: bool, allowed: bool| {
+            if set && !allowed {
+                bail!(
+                    "{flag} does not apply to `{}`",
+                    name.as_deref().unwrap_or("")
+                );
+            }
+            Ok(())
+        };
+        let is = |command: &str| name.as_deref() == Some(command);
+        used("--intensity", intensity.is_some(), is("state"))?;
+        used("--priority", priority.is_some(), is("state"))?;
+        used("--for", duration.is_some(), is("state"))?;
+        used("--hold", hold, is("state"))?;
+        if hold && duration.is_some() {
+            bail!("--hold and --for cannot be used together");
+        }
+        used("--strength", strength.is_some(), is("pulse"))?;
+        used("--json", json, is("status"))?;
+
+        Ok(Self {
+            help,
+            socket,
+            config,
+            command,
+        })
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("{flag} requires a value"))
+}
+
+fn parse_unit(value: &str, flag: &str) -> anyhow::Result<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|v| (0.0..=1.0).contains(v))
+        .ok_or_else(|| anyhow!("invalid {flag} value {value:?}; expected 0 to 1"))
+}
+
+/// `500ms`, `10s`, `2m` or a plain number of seconds.
+fn parse_duration(value: &str) -> anyhow::Result<Duration> {
+    let invalid = || anyhow!("invalid --for value {value:?}; expected e.g. 500ms, 10s or 2m");
+    let (number, unit) = match value.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number = number.parse::<f64>().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| {
+            !duration.is_zero() && *duration <= Duration::from_millis(ipc::MAX_DURATION_MS)
+        })
+        .ok_or_else(invalid)
+}
+
+/// Runs `sentinel-renderer ctl` with the arguments after `ctl`, returning the exit code.
+pub fn main(args: impl IntoIterator<Item = String>) -> i32 {
+    let options = match CtlOptions::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("sentinel-renderer ctl: {err}");
+            eprintln!("{USAGE}");
+            return 2;
+        }
+    };
+    let Some(command) = options.command.filter(|_| !options.help) else {
+        println!("{USAGE}");
+        return if options.help { 0 } else { 2 };
+    };
+    match run(options.socket, options.config, command) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("sentinel-renderer ctl: {err:#}");
+            1
+        }
+    }
+}
+
+fn run(socket: Option<PathBuf>, config: Option<PathBuf>, command: Command) -> anyhow::Result<()> {
+    let path = match socket {
+        Some(path) => path,
+        None => {
+            let config = config::Config::load(config.as_deref())?;
+            control::socket_path(config.control_socket.as_deref()).ok_or_else(|| {
+                anyhow!(
+                    "no control socket configured; set `control_socket` in renderer.toml or \
+                     SENTINEL_CONTROL_SOCKET, or pass --socket"
+                )
+            })?
+        }
+    };
+    let mut conn = Connection::open(&path)?;
+
+    let hello = conn.expect("hello")?;
+    let version = hello["version"].as_u64().unwrap_or(1);
+    if version < u64::from(ipc::MIN_PROTOCOL_VERSION) {
+        bail!("the renderer speaks protocol version {version}, which is too old");
+    }
+    let accepts = |message_type: &str| {
+        hello["messages"]
+            .as_array()
+            .is_some_and(|types| types.iter().any(|t| t == message_type))
+    };
+    if let Some(message_type) = command.message_type().filter(|t| !accepts(t)) {
+        bail!("the renderer does not support `{message_type}`; is it older than this command?");
+    }
+    conn.send(&json!({ "type": "hello", "version": ipc::PROTOCOL_VERSION }))?;
+
+    match command {
+        Command::State {
+            state,
+            intensity,
+            priority,
+            duration,
+            hold,
+        } => {
+            let mut message = json!({
+                "type": "state",
+                "state": state,
+                "intensity": intensity,
+                "priority": priority,
+            });
+            if let Some(duration) = duration {
+                message["duration_ms"] = (duration.as_millis() as u64).max(1).into();
+            }
+            if hold {
+                conn.send(&message)?;
+                eprintln!("Holding {state}; interrupt to release it");
+                return conn.hold();
+            }
+            // An override outlives the connection anyway; a standing claim has to be left
+            // behind explicitly.
+            if duration.is_none() {
+                message["persist"] = true.into();
+            }
+            conn.send(&message)?;
+            conn.finish()
+        }
+        Command::Pulse { strength } => {
+            conn.send(&json!({ "type": "pulse", "strength": strength }))?;
+            conn.finish()
+        }
+        Command::Pause => {
+            conn.send(&json!({ "type": "pause" }))?;
+            conn.finish()
+        }
+        Command::Resume => {
+            conn.send(&json!({ "type": "resume" }))?;
+            conn.finish()
+        }
+        Command::Status { json } => {
+            let status = conn.expect("status")?;
+            if json {
+                println!("{status}");
+            } else {
+                print_status(&status);
+            }
+            Ok(())
+        }
+        Command::Capture { name } => {
+            conn.send(&json!({ "type": "capture", "path": name }))?;
+            conn.finish()
+        }
+    }
+}
+
+fn print_status(status: &Value) {
+    let state = status["state"].as_str().unwrap_or("?");
+    let target = status["target_state"].as_str().unwrap_or(state);
+    if target == state {
+        println!("state:     {state}");
+    } else {
+        println!(
+            "state:     {state} -> {target} ({:.0}%)",
+            status["blend"].as_f64().unwrap_or(0.0) * 100.0
+        );
+    }
+    println!(
+        "intensity: {:.2}",
+        status["intensity"].as_f64().unwrap_or(0.0)
+    );
+    println!("fps:       {:.1}", status["fps"].as_f64().unwrap_or(0.0));
+    let flag = |key: &str| status[key].as_bool().unwrap_or(false);
+    println!(
+        "paused:    {}",
+        if flag("held") {
+            "yes, by a client"
+        } else if flag("paused") {
+            "yes, no output visible"
+        } else {
+            "no"
+        }
+    );
+    let outputs: Vec<String> = status["outputs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|output| {
+            format!(
+                "{} {}x{}",
+                output["name"].as_str().unwrap_or("?"),
+                output["width"],
+                output["height"]
+            )
+        })
+        .collect();
+    println!(
+        "outputs:   {}",
+        if outputs.is_empty() {
+            "none".to_string()
+        } else {
+            outputs.join(", ")
+        }
+    );
+}
+
+/// A blocking connection to the control socket, reading the renderer's messages line by line.
+struct Connection {
+    reader: BufReader<UnixStream>,
+    stream: UnixStream,
+}
+
+impl Connection {
+    fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        let stream = UnixStream::connect(path).with_context(|| {
+            format!(
+                "failed to connect to {}; is the renderer running with a control socket?",
+                path.display()
+            )
+        })?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        })
+    }
+
+    fn send(&mut self, message: &Value) -> anyhow::Result<()> {
+        let mut line = message.to_string();
+        line.push('\n');
+        self.stream
+            .write_all(line.as_bytes())
+            .context("failed to write to the renderer")
+    }
+
+    /// The renderer's next message, or `None` at the end of the connection or once
+    /// `deadline` has passed. An `error` message becomes an error.
+    fn next(&mut self, deadline: Option<Instant>) -> anyhow::Result<Option<Value>> {
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if !timeout.is_zero() => Some(timeout),
+                    _ => return Ok(None),
+                },
+                None => None,
+            };
+            self.stream.set_read_timeout(timeout)?;
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(err) => return Err(err).context("failed to read from the renderer"),
+            }
+            let Ok(message) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if message["type"] == "error" {
+                bail!(
+                    "the renderer rejected the request: {}",
+                    message["message"].as_str().unwrap_or("unknown error")
+                );
+            }
+            return Ok(Some(message));
+        }
+    }
+
+    /// Waits for the renderer's next message of type `message_type`.
+    fn expect(&mut self, message_type: &str) -> anyhow::Result<Value> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while let Some(message) = self.next(Some(deadline))? {
+            if message["type"] == message_type {
+                return Ok(message);
+            }
+        }
+        bail!("no `{message_type}` message from the renderer")
+    }
+
+    /// Closes the sending side and reads until the renderer hangs up, which it does once it
+    /// has handled everything sent, reporting any error it replied with.
+    fn finish(mut self) -> anyhow::Result<()> {
+        self.stream.shutdown(Shutdown::Write)?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while self.next(Some(deadline))?.is_some() {}
+        Ok(())
+    }
+
+    /// Keeps the connection, and with it the state claim, open until the renderer goes away.
+    fn hold(mut self) -> anyhow::Result<()> {
+        while self.next(None)?.is_some() {}
+        bail!("the renderer closed the connection")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<CtlOptions> {
+        CtlOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_err(args: &[&str]) -> String {
+        format!("{:#}", parse(args).expect_err("parse should fail"))
+    }
+
+    #[test]
+    fn durations_with_units() {
+        assert_eq!(parse_duration("10s").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("3").unwrap(), Duration::from_secs(3));
+        assert_eq!(parse_duration("1.5").unwrap(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for value in [
+            "", "s", "10h", "ten", "-1s", "0", "0ms", "0s", "25h", "1441m",
+        ] {
+            assert!(
+                parse_duration(value).is_err(),
+                "{value:?} should be rejected"
+            );
+        }
+        assert!(parse_duration("1440m").is_ok());
+    }
+
+    #[test]
+    fn state_defaults() {
+        let options = parse(&["state", "alert"]).unwrap();
+        match options.command {
+            Some(Command::State {
+                state,
+                intensity,
+                priority,
+                duration,
+                hold,
+            }) => {
+                assert_eq!(state, "alert");
+                assert_eq!(intensity, 1.0);
+                assert_eq!(priority, 0);
+                assert_eq!(duration, None);
+                assert!(!hold);
+            }
+            other => panic!("unexpected command {other:?}"),
+        }
+    }
+
+    #[test]
+    fn state_flags() {
+        let options = parse(&[
+            "--socket",
+            "/tmp/s.sock",
+            "state",
+            "sleepy",
+            "--intensity",
+            "0.25",
+            "--priority",
+            "-3",
+            "--for",
+            "500ms",
+        ])
+        .unwrap();
+        assert_eq!(options.socket, Some(PathBuf::from("/tmp/s.sock")));
+        match options.command {
+            Some(Command::State {
+                intensity,
+                priority,
+                duration,
+                ..
+            }) => {
+                assert_eq!(intensity, 0.25);
+                assert_eq!(priority, -3);
+                assert_eq!(duration, Some(Duration::from_millis(500)));
+            }
+            other => panic!("unexpected command {other:?}"),
+        }
+        assert!(matches!(
+            parse(&["state", "idle", "--hold"]).unwrap().command,
+            Some(Command::State { hold: true, .. })
+        ));
+    }
+
+    #[test]
+    fn intensity_out_of_range() {
+        for value in ["1.5", "-0.1", "NaN", "loud"] {
+            let err = parse_err(&["state", "alert", "--intensity", value]);
+            assert!(err.contains("--intensity"), "{err}");
+        }
+        assert!(parse(&["state", "alert", "--intensity", "0"]).is_ok());
+        assert!(parse(&["state", "alert", "--intensity", "1"]).is_ok());
+        assert!(parse_err(&["pulse", "--strength", "2"]).contains("--strength"));
+    }
+
+    #[test]
+    fn missing_values() {
+        for flag in [
+            "--socket",
+            "--config",
+            "--intensity",
+            "--strength",
+            "--priority",
+            "--for",
+        ] {
+            assert_eq!(
+                parse_err(&["state", "alert", flag]),
+                format!("{flag} requires a value")
+            );
+        }
+        assert_eq!(parse_err(&["state"]), "`state` requires a state name");
+    }
+
+    #[test]
+    fn unknown_arguments() {
+        assert_eq!(
+            parse_err(&["status", "--verbose"]),
+            "unknown argument \"--verbose\""
+        );
+        assert_eq!(parse_err(&["dance"]), "unknown command \"dance\"");
+        assert!(parse_err(&["state", "grumpy"]).starts_with("unknown state \"grumpy\""));
+        assert_eq!(parse_err(&["pause", "now"]), "unexpected argument \"now\"");
+    }
+
+    #[test]
+    fn flags_for_other_commands() {
+        assert_eq!(
+            parse_err(&["pulse", "--intensity", "0.5"]),
+            "--intensity does not apply to `pulse`"
+        );
+        assert_eq!(
+            parse_err(&["status", "--for", "1s"]),
+            "--for does not apply to `status`"
+        );
+        assert_eq!(
+            parse_err(&["pause", "--hold"]),
+            "--hold does not apply to `pause`"
+        );
+        assert_eq!(
+            parse_err(&["state", "idle", "--json"]),
+            "--json does not apply to `state`"
+        );
+        assert_eq!(
+            parse_err(&["state", "idle", "--hold", "--for", "1s"]),
+            "--hold and --for cannot be used together"
+        );
+    }
+
+    #[test]
+    fn help_and_no_command() {
+        let options = parse(&["--help"]).unwrap();
+        assert!(options.help);
+        assert!(options.command.is_none());
+        assert!(parse(&[]).unwrap().command.is_none());
+    }
+}
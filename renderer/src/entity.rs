@@ -0,0 +1,917 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, ParticlesConfig},
+    gpu::{SwarmStats, Uniforms},
+};
+
+#[derive(Debug, Copy, Clone)]
+struct SmoothValue {
+    current: f32,
+    from: f32,
+    target: f32,
+    started_at: Instant,
+}
+
+impl SmoothValue {
+    fn new(value: f32, now: Instant) -> Self {
+        Self {
+            current: value,
+            from: value,
+            target: value,
+            started_at: now,
+        }
+    }
+
+    fn set_target(&mut self, target: f32, now: Instant) {
+        if self.target.to_bits() == target.to_bits() {
+            return;
+        }
+        self.from = self.current;
+        self.target = target;
+        self.started_at = now;
+    }
+
+    fn update(&mut self, now: Instant, duration: Duration) {
+        if self.current.to_bits() == self.target.to_bits() {
+            return;
+        }
+
+        let duration_s = duration.as_secs_f32();
+        if duration_s <= 0.000_1 {
+            self.current = self.target;
+            return;
+        }
+
+        let elapsed_s = now.duration_since(self.started_at).as_secs_f32();
+        let mut t = (elapsed_s / duration_s).clamp(0.0, 1.0);
+        t = t * t * (3.0 - 2.0 * t);
+        self.current = self.from + (self.target - self.from) * t;
+        if t >= 1.0 {
+            self.current = self.target;
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct StateBlend {
+    current_state: u32,
+    target_state: u32,
+    blend: SmoothValue,
+}
+
+impl StateBlend {
+    fn new(state: u32, now: Instant) -> Self {
+        Self {
+            current_state: state.min(5),
+            target_state: state.min(5),
+            blend: SmoothValue::new(0.0, now),
+        }
+    }
+
+    fn set_target(&mut self, target_state: u32, now: Instant) {
+        let target_state = target_state.min(5);
+        if self.target_state == target_state {
+            return;
+        }
+
+        if self.current_state != self.target_state && self.blend.current >= 0.5 {
+            self.current_state = self.target_state;
+        }
+
+        self.target_state = target_state;
+        if self.current_state == self.target_state {
+            self.blend = SmoothValue::new(0.0, now);
+            return;
+        }
+
+        self.blend = SmoothValue::new(0.0, now);
+        self.blend.set_target(1.0, now);
+    }
+
+    fn update(&mut self, now: Instant, duration: Duration) {
+        if self.current_state == self.target_state {
+            self.blend = SmoothValue::new(0.0, now);
+            return;
+        }
+
+        self.blend.update(now, duration);
+        if self.blend.current >= 1.0 {
+            self.current_state = self.target_state;
+            self.blend = SmoothValue::new(0.0, now);
+        }
+    }
+
+    fn blend_factor(&self) -> f32 {
+        self.blend.current
+    }
+}
+
+/// CPU-side motion of the swarm anchor (position and scale) for one state.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MotionParams {
+    pub base_scale: f32,
+    pub scale_pulse: f32,
+    pub pulse_speed: f32,
+    pub drift_amp: [f32; 2],
+    pub drift_speed: f32,
+    pub bounce_mix: f32,
+    pub bounce_speed: f32,
+    pub base_offset: [f32; 2],
+    pub smooth_time: f32,
+    pub gaze_mix: f32,
+}
+
+impl MotionParams {
+    pub fn defaults(state: u32) -> Self {
+        match state {
+            1 => Self {
+                base_scale: 1.25,
+                scale_pulse: 0.1,
+                pulse_speed: 1.1,
+                drift_amp: [0.16, 0.12],
+                drift_speed: 0.45,
+                bounce_mix: 0.6,
+                bounce_speed: 0.25,
+                base_offset: [0.0, 0.05],
+                smooth_time: 0.7,
+                gaze_mix: 1.0,
+            },
+            2 => Self {
+                base_scale: 0.7,
+                scale_pulse: 0.02,
+                pulse_speed: 0.5,
+                drift_amp: [0.02, 0.015],
+                drift_speed: 0.12,
+                bounce_mix: 0.0,
+                bounce_speed: 0.1,
+                base_offset: [0.0, 0.0],
+                smooth_time: 0.8,
+                gaze_mix: 0.9,
+            },
+            3 => Self {
+                base_scale: 1.05,
+                scale_pulse: 0.16,
+                pulse_speed: 1.6,
+                drift_amp: [0.12, 0.1],
+                drift_speed: 0.8,
+                bounce_mix: 0.4,
+                bounce_speed: 0.9,
+                base_offset: [0.02, 0.0],
+                smooth_time: 0.45,
+                gaze_mix: 0.6,
+            },
+            4 => Self {
+                base_scale: 1.45,
+                scale_pulse: 0.22,
+                pulse_speed: 2.2,
+                drift_amp: [0.2, 0.18],
+                drift_speed: 1.2,
+                bounce_mix: 0.8,
+                bounce_speed: 1.1,
+                base_offset: [0.0, 0.1],
+                smooth_time: 0.35,
+                gaze_mix: 0.7,
+            },
+            5 => Self {
+                base_scale: 0.6,
+                scale_pulse: 0.02,
+                pulse_speed: 0.35,
+                drift_amp: [0.03, 0.025],
+                drift_speed: 0.08,
+                bounce_mix: 0.0,
+                bounce_speed: 0.1,
+                base_offset: [0.0, -0.22],
+                smooth_time: 1.4,
+                gaze_mix: 0.35,
+            },
+            _ => Self {
+                base_scale: 1.0,
+                scale_pulse: 0.04,
+                pulse_speed: 0.6,
+                drift_amp: [0.06, 0.04],
+                drift_speed: 0.2,
+                bounce_mix: 0.0,
+                bounce_speed: 0.15,
+                base_offset: [0.0, 0.0],
+                smooth_time: 1.1,
+                gaze_mix: 0.85,
+            },
+        }
+    }
+
+    fn with_intensity(self, intensity: f32) -> Self {
+        let intensity = intensity.clamp(0.0, 1.0);
+        let energy = 0.35 + 0.65 * intensity;
+
+        let mut params = self;
+        params.drift_amp[0] *= energy;
+        params.drift_amp[1] *= energy;
+        params.scale_pulse *= 0.3 + 0.7 * intensity;
+        params.drift_speed *= 0.4 + 0.6 * intensity;
+        params.bounce_speed *= 0.4 + 0.6 * intensity;
+        params.bounce_mix *= 0.2 + 0.8 * intensity;
+        params.pulse_speed *= 0.5 + 0.5 * intensity;
+
+        params
+    }
+
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Self {
+            base_scale: lerp(self.base_scale, other.base_scale, t),
+            scale_pulse: lerp(self.scale_pulse, other.scale_pulse, t),
+            pulse_speed: lerp(self.pulse_speed, other.pulse_speed, t),
+            drift_amp: lerp2(self.drift_amp, other.drift_amp, t),
+            drift_speed: lerp(self.drift_speed, other.drift_speed, t),
+            bounce_mix: lerp(self.bounce_mix, other.bounce_mix, t),
+            bounce_speed: lerp(self.bounce_speed, other.bounce_speed, t),
+            base_offset: lerp2(self.base_offset, other.base_offset, t),
+            smooth_time: lerp(self.smooth_time, other.smooth_time, t),
+            gaze_mix: lerp(self.gaze_mix, other.gaze_mix, t),
+        }
+    }
+}
+
+/// Per-particle simulation and shading parameters for one state.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SynapticParams {
+    pub damping: f32,
+    pub noise_strength: f32,
+    pub attraction: f32,
+    pub speed: f32,
+    pub trail_fade: f32,
+    pub glow_intensity: f32,
+    pub color_shift: f32,
+}
+
+impl SynapticParams {
+    pub fn defaults(state: u32) -> Self {
+        match state {
+            1 => Self {
+                damping: 0.995,
+                noise_strength: 9.0,
+                attraction: 0.45,
+                speed: 1.5,
+                trail_fade: 0.992,
+                glow_intensity: 1.05,
+                color_shift: 0.15,
+            },
+            2 => Self {
+                damping: 0.9992,
+                noise_strength: 1.2,
+                attraction: 2.0,
+                speed: 0.45,
+                trail_fade: 0.9985,
+                glow_intensity: 0.7,
+                color_shift: -0.08,
+            },
+            3 => Self {
+                damping: 0.993,
+                noise_strength: 14.0,
+                attraction: 0.35,
+                speed: 1.9,
+                trail_fade: 0.989,
+                glow_intensity: 1.2,
+                color_shift: 0.22,
+            },
+            4 => Self {
+                damping: 0.991,
+                noise_strength: 18.0,
+                attraction: 0.1,
+                speed: 2.6,
+                trail_fade: 0.984,
+                glow_intensity: 1.35,
+                color_shift: 0.3,
+            },
+            5 => Self {
+                damping: 0.9999,
+                noise_strength: 0.8,
+                attraction: 0.2,
+                speed: 0.3,
+                trail_fade: 0.9992,
+                glow_intensity: 0.6,
+                color_shift: -0.15,
+            },
+            _ => Self {
+                damping: 0.9975,
+                noise_strength: 4.5,
+                attraction: 0.35,
+                speed: 0.9,
+                trail_fade: 0.996,
+                glow_intensity: 0.85,
+                color_shift: 0.0,
+            },
+        }
+    }
+
+    fn with_intensity(self, intensity: f32) -> Self {
+        let intensity = intensity.clamp(0.0, 1.0);
+        let mut params = self;
+        params.glow_intensity *= 0.45 + 0.55 * intensity;
+        params.color_shift *= 0.35 + 0.65 * intensity;
+
+        params
+    }
+
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Self {
+            damping: lerp(self.damping, other.damping, t),
+            noise_strength: lerp(self.noise_strength, other.noise_strength, t),
+            attraction: lerp(self.attraction, other.attraction, t),
+            speed: lerp(self.speed, other.speed, t),
+            trail_fade: lerp(self.trail_fade, other.trail_fade, t),
+            glow_intensity: lerp(self.glow_intensity, other.glow_intensity, t),
+            color_shift: lerp(self.color_shift, other.color_shift, t),
+        }
+    }
+}
+
+/// Multipliers the simulation shader applies on top of [`SynapticParams`]: how strongly
+/// particles are pulled to the swarm center and the goal, how much flow noise and damping
+/// they get, and the gain of the state's signature force (probing, orbiting, darting, ...).
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwarmParams {
+    pub center_pull: f32,
+    pub noise_scale: f32,
+    pub goal_pull: f32,
+    pub damping_scale: f32,
+    pub force_gain: f32,
+}
+
+impl SwarmParams {
+    pub fn defaults(state: u32) -> Self {
+        let (center_pull, noise_scale, goal_pull, damping_scale) = match state {
+            1 => (0.45, 1.2, 0.9, 1.0),
+            2 => (1.8, 0.25, 0.2, 0.85),
+            3 => (0.6, 1.4, 0.5, 0.95),
+            4 => (-0.4, 1.6, 0.2, 0.95),
+            5 => (0.15, 0.2, 0.1, 0.75),
+            _ => (0.55, 0.9, 0.35, 1.0),
+        };
+        Self {
+            center_pull,
+            noise_scale,
+            goal_pull,
+            damping_scale,
+            force_gain: 1.0,
+        }
+    }
+
+    /// Blended multipliers in the shader's `mods` layout. The force gains are not blended
+    /// here because the shader blends the two states' forces itself.
+    fn blended_mods(self, other: Self, t: f32) -> [f32; 4] {
+        [
+            lerp(self.center_pull, other.center_pull, t),
+            lerp(self.noise_scale, other.noise_scale, t),
+            lerp(self.goal_pull, other.goal_pull, t),
+            lerp(self.damping_scale, other.damping_scale, t),
+        ]
+    }
+}
+
+/// Boids-style forces between neighboring particles, used when `particles.flocking` is on:
+/// each weight applies to the neighbors within its radius (in simulation units, where the
+/// swarm is usually about one across).
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlockParams {
+    /// Pushes particles apart that come closer than `separation_radius`.
+    pub separation: f32,
+    pub separation_radius: f32,
+    /// Steers each particle towards its neighbors' average velocity.
+    pub alignment: f32,
+    pub alignment_radius: f32,
+    /// Pulls each particle towards its neighbors' average position.
+    pub cohesion: f32,
+    pub cohesion_radius: f32,
+}
+
+impl FlockParams {
+    pub fn defaults(state: u32) -> Self {
+        let (separation, separation_radius, alignment, alignment_radius, cohesion, cohesion_radius) =
+            match state {
+                1 => (1.0, 0.12, 0.6, 0.3, 0.3, 0.3),
+                2 => (1.5, 0.08, 1.5, 0.4, 1.2, 0.4),
+                3 => (1.2, 0.15, 0.3, 0.2, 0.2, 0.25),
+                4 => (2.0, 0.2, 0.8, 0.3, 0.1, 0.2),
+                5 => (0.6, 0.1, 0.3, 0.2, 0.5, 0.35),
+                _ => (1.0, 0.12, 0.4, 0.25, 0.3, 0.3),
+            };
+        Self {
+            separation,
+            separation_radius,
+            alignment,
+            alignment_radius,
+            cohesion,
+            cohesion_radius,
+        }
+    }
+
+    /// Blended radii and weights, each in separation, alignment, cohesion order.
+    fn blended(self, other: Self, t: f32) -> ([f32; 3], [f32; 3]) {
+        (
+            [
+                lerp(self.separation_radius, other.separation_radius, t),
+                lerp(self.alignment_radius, other.alignment_radius, t),
+                lerp(self.cohesion_radius, other.cohesion_radius, t),
+            ],
+            [
+                lerp(self.separation, other.separation, t),
+                lerp(self.alignment, other.alignment, t),
+                lerp(self.cohesion, other.cohesion, t),
+            ],
+        )
+    }
+}
+
+/// Everything that defines how one entity state looks and moves.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatePreset {
+    pub motion: MotionParams,
+    pub synaptic: SynapticParams,
+    pub swarm: SwarmParams,
+    pub flock: FlockParams,
+}
+
+impl StatePreset {
+    pub fn defaults(state: u32) -> Self {
+        Self {
+            motion: MotionParams::defaults(state),
+            synaptic: SynapticParams::defaults(state),
+            swarm: SwarmParams::defaults(state),
+            flock: FlockParams::defaults(state),
+        }
+    }
+}
+
+/// Presets for all six states, indexed by the state's numeric id.
+#[derive(Debug, Copy, Clone)]
+pub struct Presets(pub [StatePreset; 6]);
+
+impl Default for Presets {
+    fn default() -> Self {
+        Self(std::array::from_fn(|state| {
+            StatePreset::defaults(state as u32)
+        }))
+    }
+}
+
+impl Presets {
+    fn get(&self, state: u32) -> &StatePreset {
+        &self.0[state.min(5) as usize]
+    }
+}
+
+/// Trail colors: each channel is `abs(sin(frequency * phase + phase_offset) * amplitude +
+/// offset)`, with the phase advancing slowly over time and along the particle index.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Palette {
+    pub frequency: [f32; 3],
+    pub phase: [f32; 3],
+    pub amplitude: f32,
+    pub offset: f32,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            frequency: [2.0, 3.4, 1.2],
+            phase: [0.8, 0.0, 1.2],
+            amplitude: 0.7,
+            offset: 0.3,
+        }
+    }
+}
+
+const GAZE_SMOOTH_TIME: Duration = Duration::from_millis(400);
+
+/// Smoothed gaze point from the observer. `weight` fades in when gaze messages arrive and
+/// back out once they stop for longer than the configured timeout.
+#[derive(Debug, Copy, Clone)]
+struct GazeState {
+    x: SmoothValue,
+    y: SmoothValue,
+    weight: SmoothValue,
+    last_seen: Option<Instant>,
+    timeout: Duration,
+}
+
+impl GazeState {
+    fn new(now: Instant, timeout: Duration) -> Self {
+        Self {
+            x: SmoothValue::new(0.5, now),
+            y: SmoothValue::new(0.5, now),
+            weight: SmoothValue::new(0.0, now),
+            last_seen: None,
+            timeout,
+        }
+    }
+
+    /// `x`/`y` use protocol coordinates: (0,0) center, (-1,-1) top-left, (1,1) bottom-right.
+    fn set_target(&mut self, x: f32, y: f32, now: Instant) {
+        if !x.is_finite() || !y.is_finite() {
+            return;
+        }
+        let x = (0.5 + 0.5 * x).clamp(0.05, 0.95);
+        let y = (0.5 + 0.5 * y).clamp(0.05, 0.95);
+        if self.weight.current <= 0.0 {
+            // Nothing is following the old point; jump instead of sweeping across the screen.
+            self.x = SmoothValue::new(x, now);
+            self.y = SmoothValue::new(y, now);
+        } else {
+            self.x.set_target(x, now);
+            self.y.set_target(y, now);
+        }
+        self.weight.set_target(1.0, now);
+        self.last_seen = Some(now);
+    }
+
+    fn update(&mut self, now: Instant, fade: Duration) {
+        let expired = self
+            .last_seen
+            .is_some_and(|seen| now.duration_since(seen) >= self.timeout);
+        if expired {
+            self.weight.set_target(0.0, now);
+            self.last_seen = None;
+        }
+
+        self.x.update(now, GAZE_SMOOTH_TIME);
+        self.y.update(now, GAZE_SMOOTH_TIME);
+        self.weight.update(now, fade);
+    }
+
+    fn target(&self) -> Option<([f32; 2], f32)> {
+        if self.weight.current <= 0.0 {
+            return None;
+        }
+        Some(([self.x.current, self.y.current], self.weight.current))
+    }
+}
+
+/// Simulation space to screen UV at scale 1: the camera in render.wgsl sits at z = 2.5 and
+/// casts rays through a plane 0.5 in front of it.
+const WORLD_TO_SCREEN: f32 = 0.2;
+/// How long a `pulse` takes to swell and settle again.
+const PULSE_DURATION: Duration = Duration::from_millis(1200);
+/// How much a full-strength pulse grows the swarm.
+const PULSE_SCALE: f32 = 0.4;
+/// Closest the swarm's centre gets to the canvas edge, in addition to its spread.
+const SWARM_EDGE_MARGIN: f32 = 0.05;
+
+#[derive(Debug, Copy, Clone)]
+struct MotionState {
+    pos_x: SmoothValue,
+    pos_y: SmoothValue,
+    scale: SmoothValue,
+    /// Where the swarm actually is, as last measured on the GPU.
+    swarm: Option<SwarmStats>,
+}
+
+impl MotionState {
+    fn new(now: Instant) -> Self {
+        Self {
+            pos_x: SmoothValue::new(0.5, now),
+            pos_y: SmoothValue::new(0.5, now),
+            scale: SmoothValue::new(1.0, now),
+            swarm: None,
+        }
+    }
+
+    /// `aspect` is the canvas' width over its height.
+    fn update(
+        &mut self,
+        now: Instant,
+        params: MotionParams,
+        t: f32,
+        gaze: Option<([f32; 2], f32)>,
+        aspect: f32,
+    ) -> ([f32; 2], f32) {
+        let smooth_time = params.smooth_time.max(0.05);
+        let smooth = Duration::from_secs_f32(smooth_time);
+        self.pos_x.update(now, smooth);
+        self.pos_y.update(now, smooth);
+        self.scale.update(now, smooth);
+
+        let mut target_pos = target_position(params, t, gaze);
+        if let Some(stats) = self.swarm {
+            target_pos = keep_on_screen(target_pos, stats, self.scale.current, aspect);
+        }
+        let target_scale = target_scale(params, t);
+
+        self.pos_x.set_target(target_pos[0], now);
+        self.pos_y.set_target(target_pos[1], now);
+        self.scale.set_target(target_scale, now);
+
+        ([self.pos_x.current, self.pos_y.current], self.scale.current)
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp2(a: [f32; 2], b: [f32; 2], t: f32) -> [f32; 2] {
+    [lerp(a[0], b[0], t), lerp(a[1], b[1], t)]
+}
+
+fn tri_wave(t: f32) -> f32 {
+    let f = t.fract();
+    if f < 0.5 {
+        f * 2.0
+    } else {
+        (1.0 - f) * 2.0
+    }
+}
+
+fn target_position(params: MotionParams, t: f32, gaze: Option<([f32; 2], f32)>) -> [f32; 2] {
+    let base = [
+        (0.5 + params.base_offset[0]).clamp(0.05, 0.95),
+        (0.5 + params.base_offset[1]).clamp(0.05, 0.95),
+    ];
+    let drift = [
+        (t * params.drift_speed).sin() * params.drift_amp[0],
+        (t * params.drift_speed * 0.83 + 1.7).cos() * params.drift_amp[1],
+    ];
+    let bounce = [
+        lerp(0.08, 0.92, tri_wave(t * params.bounce_speed + 0.13)),
+        lerp(0.08, 0.92, tri_wave(t * params.bounce_speed * 0.93 + 0.57)),
+    ];
+
+    let mut pos = [base[0] + drift[0], base[1] + drift[1]];
+    pos[0] = lerp(pos[0], bounce[0], params.bounce_mix);
+    pos[1] = lerp(pos[1], bounce[1], params.bounce_mix);
+
+    // Gaze replaces the base anchor and bounce path, but keeps the drift so the swarm still
+    // breathes around the point it is looking at.
+    if let Some((point, weight)) = gaze {
+        let mix = (params.gaze_mix * weight).clamp(0.0, 1.0);
+        pos[0] = lerp(pos[0], point[0] + drift[0], mix);
+        pos[1] = lerp(pos[1], point[1] + drift[1], mix);
+    }
+
+    pos[0] = pos[0].clamp(0.05, 0.95);
+    pos[1] = pos[1].clamp(0.05, 0.95);
+    pos
+}
+
+/// Moves the anchor `target` so the swarm, which is drawn `stats.center` away from its anchor,
+/// keeps its centre on the canvas and as much of its spread as fits.
+fn keep_on_screen(target: [f32; 2], stats: SwarmStats, scale: f32, aspect: f32) -> [f32; 2] {
+    let extent = [aspect, 1.0];
+    std::array::from_fn(|axis| {
+        let to_screen = WORLD_TO_SCREEN * scale / extent[axis];
+        let offset = stats.center[axis] * to_screen;
+        let margin = (SWARM_EDGE_MARGIN + stats.spread * to_screen).min(0.45);
+        ((target[axis] + offset).clamp(margin, 1.0 - margin) - offset).clamp(0.05, 0.95)
+    })
+}
+
+/// A one-shot swell of the swarm's size and intensity.
+#[derive(Debug, Copy, Clone)]
+struct Pulse {
+    start: Instant,
+    strength: f32,
+}
+
+impl Pulse {
+    /// How far the pulse is swollen at `now`, from 0 to `strength`: a quick rise and a slower
+    /// fall. `None` once it is over.
+    fn amount(&self, now: Instant) -> Option<f32> {
+        let x =
+            now.saturating_duration_since(self.start).as_secs_f32() / PULSE_DURATION.as_secs_f32();
+        (x < 1.0).then(|| self.strength * (x * 8.0).min(1.0) * (1.0 - x) * (1.0 - x))
+    }
+}
+
+fn target_scale(params: MotionParams, t: f32) -> f32 {
+    let pulse = (t * params.pulse_speed).sin();
+    let wobble = (t * (params.pulse_speed * 0.4 + 0.7)).sin();
+    (params.base_scale + params.scale_pulse * pulse + params.scale_pulse * 0.35 * wobble)
+        .clamp(0.35, 2.5)
+}
+
+/// Everything that drives the entity's look over time, independent of where it is presented.
+#[derive(Debug, Copy, Clone)]
+pub struct Entity {
+    state: StateBlend,
+    intensity: SmoothValue,
+    motion: MotionState,
+    gaze: GazeState,
+    cycle_states: bool,
+    transition_duration: Duration,
+    presets: Presets,
+    palette: Palette,
+    trail_steps: u32,
+    flocking: bool,
+    pulse: Option<Pulse>,
+}
+
+impl Entity {
+    pub fn new(
+        state: u32,
+        intensity: f32,
+        now: Instant,
+        transition_duration: Duration,
+        gaze_timeout: Duration,
+        cycle_states: bool,
+        presets: Presets,
+    ) -> Self {
+        Self {
+            state: StateBlend::new(state, now),
+            intensity: SmoothValue::new(intensity.clamp(0.0, 1.0), now),
+            motion: MotionState::new(now),
+            gaze: GazeState::new(now, gaze_timeout),
+            cycle_states,
+            transition_duration,
+            presets,
+            palette: Palette::default(),
+            trail_steps: ParticlesConfig::default().trail_steps,
+            flocking: ParticlesConfig::default().flocking,
+            pulse: None,
+        }
+    }
+
+    /// Uses the timings and presets from `config`, and reads the initial state from the
+    /// `SENTINEL_ENTITY_*` environment variables.
+    pub fn from_config(config: &Config, now: Instant) -> Self {
+        let state = std::env::var("SENTINEL_ENTITY_STATE")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(0)
+            .min(5);
+        let intensity = std::env::var("SENTINEL_ENTITY_INTENSITY")
+            .ok()
+            .and_then(|v| v.parse::<f32>().ok())
+            .unwrap_or(1.0)
+            .clamp(0.0, 1.0);
+        let cycle_states = std::env::var("SENTINEL_ENTITY_CYCLE")
+            .ok()
+            .is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+
+        let mut entity = Self::new(
+            state,
+            intensity,
+            now,
+            config.transition_duration,
+            config.gaze_timeout,
+            cycle_states,
+            config.presets,
+        );
+        entity.palette = config.palette;
+        entity.trail_steps = config.particles.trail_steps;
+        entity.flocking = config.particles.flocking;
+        entity
+    }
+
+    /// Switches to the timings, presets, palette and particle settings of a reloaded config.
+    /// The current state, intensity and motion carry on, so the change shows up as a
+    /// continuation rather than a restart.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.transition_duration = config.transition_duration;
+        self.gaze.timeout = config.gaze_timeout;
+        self.presets = config.presets;
+        self.palette = config.palette;
+        self.trail_steps = config.particles.trail_steps;
+        self.flocking = config.particles.flocking;
+    }
+
+    /// Returns true if the target state changed.
+    pub fn set_state(&mut self, state: u32, now: Instant) -> bool {
+        if self.state.target_state == state.min(5) {
+            return false;
+        }
+        self.state.set_target(state, now);
+        true
+    }
+
+    /// Returns true if the target intensity changed.
+    pub fn set_intensity(&mut self, intensity: f32, now: Instant) -> bool {
+        let intensity = intensity.clamp(0.0, 1.0);
+        if self.intensity.target.to_bits() == intensity.to_bits() {
+            return false;
+        }
+        self.intensity.set_target(intensity, now);
+        true
+    }
+
+    /// The state being shown, the state it is blending to and how far the blend has got, as
+    /// of the last frame.
+    pub fn state(&self) -> (u32, u32, f32) {
+        (
+            self.state.current_state,
+            self.state.target_state,
+            self.state.blend_factor(),
+        )
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity.current
+    }
+
+    pub fn set_gaze(&mut self, x: f32, y: f32, now: Instant) {
+        self.gaze.set_target(x, y, now);
+    }
+
+    /// Swells the swarm once, by `strength` from 0 to 1, on top of whatever it is doing.
+    pub fn pulse(&mut self, strength: f32, now: Instant) {
+        if strength.is_finite() {
+            self.pulse = Some(Pulse {
+                start: now,
+                strength: strength.clamp(0.0, 1.0),
+            });
+        }
+    }
+
+    /// Tells the motion where the swarm actually is, so it can keep it on screen.
+    pub fn set_swarm_stats(&mut self, stats: SwarmStats) {
+        self.motion.swarm = Some(stats);
+    }
+
+    /// Advances all transitions to `now` and returns the uniforms for a frame at animation
+    /// time `t` (seconds since start).
+    pub fn frame_uniforms(
+        &mut self,
+        now: Instant,
+        t: f32,
+        width: u32,
+        height: u32,
+        frame_count: u32,
+    ) -> Uniforms {
+        if self.cycle_states {
+            let cycle_state = ((t / 8.0).floor() as u32) % 6;
+            self.state.set_target(cycle_state, now);
+        }
+
+        self.state.update(now, self.transition_duration);
+        self.intensity.update(now, self.transition_duration);
+        self.gaze.update(now, self.transition_duration);
+
+        let pulse = self.pulse.and_then(|pulse| pulse.amount(now));
+        if pulse.is_none() {
+            self.pulse = None;
+        }
+        let pulse = pulse.unwrap_or(0.0);
+
+        let blend = self.state.blend_factor();
+        let intensity = (self.intensity.current + pulse).min(1.0);
+        let preset_cur = self.presets.get(self.state.current_state);
+        let preset_tgt = self.presets.get(self.state.target_state);
+
+        let motion_params = preset_cur
+            .motion
+            .with_intensity(intensity)
+            .lerp(preset_tgt.motion.with_intensity(intensity), blend);
+        let aspect = width as f32 / height.max(1) as f32;
+        let (position, scale) =
+            self.motion
+                .update(now, motion_params, t, self.gaze.target(), aspect);
+        let scale = scale * (1.0 + PULSE_SCALE * pulse);
+
+        let syn_params = preset_cur
+            .synaptic
+            .with_intensity(intensity)
+            .lerp(preset_tgt.synaptic.with_intensity(intensity), blend);
+        let mods = preset_cur.swarm.blended_mods(preset_tgt.swarm, blend);
+        let force_gain = [preset_cur.swarm.force_gain, preset_tgt.swarm.force_gain];
+
+        let uniforms = Uniforms::new(
+            t,
+            self.state.current_state,
+            self.state.target_state,
+            blend,
+            intensity,
+            scale,
+            position,
+            width,
+            height,
+            frame_count,
+            syn_params.damping,
+            syn_params.noise_strength,
+            syn_params.attraction,
+            syn_params.speed,
+            syn_params.trail_fade,
+            syn_params.glow_intensity,
+            syn_params.color_shift,
+        )
+        .with_swarm(mods, force_gain)
+        .with_palette(
+            self.palette.frequency,
+            self.palette.phase,
+            self.palette.amplitude,
+            self.palette.offset,
+        )
+        .with_trail_steps(self.trail_steps);
+        if !self.flocking {
+            return uniforms;
+        }
+        let (radius, weight) = preset_cur.flock.blended(preset_tgt.flock, blend);
+        uniforms.with_flock(radius, weight)
+    }
+}
@@ -0,0 +1,206 @@
+//! Golden-image regression tests: each entity state is rendered offscreen on a software
+//! adapter with a fixed frame sequence and compared against `tests/golden/<state>.png`, or
+//! `tests/golden/compute/<state>.png` for the compute backend.
+//!
+//! Regenerate the references after an intentional visual change with
+//! `SENTINEL_UPDATE_GOLDEN=1 cargo test golden`. On a mismatch the actual frame and a diff
+//! image are written to `target/golden-failures/`.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    capture,
+    config::{ParticlesConfig, STATE_NAMES},
+    entity::{Entity, Presets},
+    gpu::{Backend, GpuContext, Image},
+    headless,
+};
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 96;
+const FRAMES: u32 = 60;
+
+/// Largest allowed mean per-channel difference (0-255 scale).
+const MAX_MEAN_DIFF: f64 = 1.0;
+/// A pixel counts as different when any channel differs by more than this.
+const PIXEL_THRESHOLD: u8 = 24;
+/// Largest allowed fraction of differing pixels.
+const MAX_DIFF_FRACTION: f64 = 0.005;
+
+fn golden_dir(backend: Backend) -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    match backend {
+        Backend::Fragment => dir,
+        Backend::Compute => dir.join("compute"),
+    }
+}
+
+fn failure_dir(backend: Backend) -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden-failures");
+    match backend {
+        Backend::Fragment => dir,
+        Backend::Compute => dir.join("compute"),
+    }
+}
+
+fn render_state(state: u32, backend: Backend) -> Image {
+    // Always use the fallback adapter: it is what build servers have, and hardware GPUs
+    // disagree on the shaders' sin-based hashes by more than any useful tolerance. Without
+    // one the test fails rather than passing without having checked anything.
+    let mut gpu = GpuContext::new_headless(true, backend).unwrap_or_else(|err| {
+        panic!(
+            "golden tests need a software adapter (llvmpipe or lavapipe) and none was \
+             found: {err:#}"
+        )
+    });
+
+    let mut swarm = gpu.create_swarm(ParticlesConfig::default().count);
+    let mut output = gpu.create_offscreen_output(WIDTH, HEIGHT);
+
+    let start_time = Instant::now();
+    let mut entity = Entity::new(
+        state,
+        1.0,
+        start_time,
+        Duration::from_millis(750),
+        Duration::from_secs(5),
+        false,
+        Presets::default(),
+    );
+    headless::render_frames(
+        &gpu,
+        &mut swarm,
+        &mut output,
+        &mut entity,
+        start_time,
+        FRAMES,
+        |_, _, _| {},
+    )
+    .expect("headless render failed");
+    gpu.read_frame(&output).expect("frame readback failed")
+}
+
+fn read_png(path: &Path) -> Image {
+    let file = File::open(path).unwrap_or_else(|err| {
+        panic!(
+            "missing golden image {} ({err}); run with SENTINEL_UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().expect("invalid golden PNG");
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).expect("invalid golden PNG");
+    assert_eq!(
+        info.color_type,
+        png::ColorType::Rgba,
+        "golden images must be RGBA"
+    );
+    buf.truncate(info.buffer_size());
+    Image {
+        width: info.width,
+        height: info.height,
+        rgba: buf,
+    }
+}
+
+struct Comparison {
+    mean_diff: f64,
+    diff_fraction: f64,
+    diff_image: Image,
+}
+
+fn compare(actual: &Image, expected: &Image) -> Comparison {
+    let mut total = 0u64;
+    let mut differing = 0usize;
+    let mut diff_rgba = Vec::with_capacity(actual.rgba.len());
+    for (a, e) in actual
+        .rgba
+        .chunks_exact(4)
+        .zip(expected.rgba.chunks_exact(4))
+    {
+        let channel_diff = |i: usize| a[i].abs_diff(e[i]);
+        let max = channel_diff(0).max(channel_diff(1)).max(channel_diff(2));
+        total += (0..3).map(|i| u64::from(channel_diff(i))).sum::<u64>();
+        if max > PIXEL_THRESHOLD {
+            differing += 1;
+        }
+        // Amplify so small drifts are still visible when inspecting the diff.
+        let v = max.saturating_mul(4);
+        diff_rgba.extend_from_slice(&[v, v, v, 255]);
+    }
+
+    let pixels = (actual.width * actual.height) as usize;
+    Comparison {
+        mean_diff: total as f64 / (pixels * 3) as f64,
+        diff_fraction: differing as f64 / pixels as f64,
+        diff_image: Image {
+            width: actual.width,
+            height: actual.height,
+            rgba: diff_rgba,
+        },
+    }
+}
+
+/// Compares `state`'s frame with its golden image, returning a description of the mismatch.
+fn check_state(state: u32, backend: Backend) -> Result<(), String> {
+    let name = STATE_NAMES[state as usize];
+    let actual = render_state(state, backend);
+    let golden_path = golden_dir(backend).join(format!("{name}.png"));
+
+    if std::env::var_os("SENTINEL_UPDATE_GOLDEN").is_some() {
+        capture::write_png(&golden_path, &actual).expect("failed to write golden image");
+        eprintln!("updated {}", golden_path.display());
+        return Ok(());
+    }
+
+    let expected = read_png(&golden_path);
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        return Err(format!(
+            "{name}: golden image size differs; regenerate with SENTINEL_UPDATE_GOLDEN=1"
+        ));
+    }
+
+    let result = compare(&actual, &expected);
+    if result.mean_diff > MAX_MEAN_DIFF || result.diff_fraction > MAX_DIFF_FRACTION {
+        let dir = failure_dir(backend);
+        let actual_path = dir.join(format!("{name}.actual.png"));
+        let diff_path = dir.join(format!("{name}.diff.png"));
+        capture::write_png(&actual_path, &actual).expect("failed to write actual image");
+        capture::write_png(&diff_path, &result.diff_image).expect("failed to write diff image");
+        return Err(format!(
+            "{name}: rendered frame differs from {} (mean diff {:.3} > {MAX_MEAN_DIFF} or \
+             {:.2}% pixels > {PIXEL_THRESHOLD} vs {:.2}% allowed); see {} and {}",
+            golden_path.display(),
+            result.mean_diff,
+            result.diff_fraction * 100.0,
+            MAX_DIFF_FRACTION * 100.0,
+            actual_path.display(),
+            diff_path.display(),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks every state on `backend`, reporting all mismatches rather than only the first.
+fn check_backend(backend: Backend) {
+    let failures: Vec<String> = (0..STATE_NAMES.len() as u32)
+        .filter_map(|state| check_state(state, backend).err())
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn golden_fragment() {
+    check_backend(Backend::Fragment);
+}
+
+#[test]
+fn golden_compute() {
+    check_backend(Backend::Compute);
+}
@@ -1,15 +1,88 @@
-use std::{ffi::c_void, num::NonZeroU64, ptr::NonNull};
+use std::{
+    ffi::c_void,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+    ptr::NonNull,
+    sync::mpsc::{self, TryRecvError},
+};
 
-use log::info;
+use log::{error, info};
 use raw_window_handle::{
     RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
 };
-use wgpu::util::DeviceExt;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::SIM_STEP;
 
-const STATE_TEXTURE_WIDTH: u32 = 256;
 const STATE_TEXTURE_HEIGHT: u32 = 128;
 const STATE_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
 const RENDER_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+const OFFSCREEN_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// Sprite light of the compute backend; unlike `Rgba32Float` it can be blended.
+const LIGHT_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const ADDITIVE_BLEND: wgpu::BlendComponent = wgpu::BlendComponent {
+    src_factor: wgpu::BlendFactor::One,
+    dst_factor: wgpu::BlendFactor::One,
+    operation: wgpu::BlendOperation::Add,
+};
+/// `Particle` in particles.wgsl: position and velocity, each padded to a `vec4`.
+const PARTICLE_SIZE: u64 = 32;
+/// Swarm statistics on the GPU: centroid and spread in one `vec4<f32>`.
+const STATS_SIZE: u64 = 16;
+/// Must match `@workgroup_size` in particles.wgsl.
+const PARTICLE_WORKGROUP_SIZE: u32 = 64;
+/// The flocking neighbor grid in particles.wgsl: a count for each of `GRID_CELLS` cells, then
+/// `CELL_CAPACITY` particle indices per cell.
+const GRID_CELLS: u64 = 4096;
+const CELL_CAPACITY: u64 = 32;
+/// Largest compute swarm that flocks. The grid spans 16 of the largest flocking radius across
+/// the swarm's center, and a swarm fills about a sixteenth of its cells, so beyond this the
+/// average cell is full and neighbors past `CELL_CAPACITY` go unseen.
+pub const MAX_FLOCKING_PARTICLES: u32 = (GRID_CELLS * CELL_CAPACITY / 16) as u32;
+
+/// Viewport of an output that shows its swarm's whole canvas.
+pub const FULL_VIEWPORT: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// How particles are simulated and drawn.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// State textures advanced by a full-screen fragment pass; every pixel of the render pass
+    /// looks at every particle. Runs on anything.
+    #[default]
+    Fragment,
+    /// A storage buffer of particles advanced by a compute shader and drawn as instanced
+    /// sprites, so each pixel only pays for the particles near it.
+    Compute,
+}
+
+impl Backend {
+    /// Largest supported swarm.
+    pub fn max_particles(self) -> u32 {
+        match self {
+            // The state texture has one column per particle.
+            Self::Fragment => 2048,
+            Self::Compute => 65536,
+        }
+    }
+
+    /// The shader stages this backend runs.
+    pub fn stages(self) -> &'static [ShaderStage] {
+        match self {
+            Self::Fragment => &[
+                ShaderStage::Simulation,
+                ShaderStage::Render,
+                ShaderStage::Present,
+            ],
+            Self::Compute => &[
+                ShaderStage::Particles,
+                ShaderStage::Sprites,
+                ShaderStage::Composite,
+                ShaderStage::Present,
+            ],
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -21,7 +94,8 @@ pub struct Uniforms {
     pub current_state: u32,
     pub target_state: u32,
     pub frame_count: u32,
-    _pad0: u32,
+    /// Varies the initial particle layout between independent swarms.
+    pub seed: u32,
     pub resolution: [f32; 2],
     pub position: [f32; 2],
     pub damping: f32,
@@ -31,7 +105,34 @@ pub struct Uniforms {
     pub trail_fade: f32,
     pub glow_intensity: f32,
     pub color_shift: f32,
-    _pad1: f32,
+    /// `wl_output.transform` already applied to the buffer: fragment coordinates are in buffer
+    /// space, while `resolution` and the canvas are in the surface's orientation.
+    pub transform: u32,
+    /// Blended swarm multipliers: center pull, noise, goal pull, damping.
+    pub mods: [f32; 4],
+    /// Gain of the current and target state's signature force.
+    pub force_gain: [f32; 2],
+    /// Seconds this pass covers: one simulation step, or for the render pass the time since
+    /// the output's previous frame.
+    pub delta_time: f32,
+    /// Points sampled along each particle's velocity when drawing its trail.
+    pub trail_steps: u32,
+    /// Trail palette `abs(sin(frequency * phase + offset) * amplitude + bias)`: per-channel
+    /// frequency in xyz, amplitude in w.
+    pub palette_frequency: [f32; 4],
+    /// Per-channel phase offset in xyz, bias in w.
+    pub palette_phase: [f32; 4],
+    /// The part of the swarm's canvas this output shows: offset in xy, size in zw, both as
+    /// fractions of the canvas. (0, 0, 1, 1) unless one swarm spans several outputs.
+    pub viewport: [f32; 4],
+    /// Size of the swarm being simulated or drawn; filled in from the swarm.
+    pub particle_count: u32,
+    pub _pad: [u32; 3],
+    /// Boids neighbor radii for separation, alignment and cohesion in xyz, the largest of them
+    /// in w.
+    pub flock_radius: [f32; 4],
+    /// Weights of the same three forces in xyz; all zero unless flocking is on.
+    pub flock_weight: [f32; 4],
 }
 
 impl Uniforms {
@@ -65,7 +166,7 @@ impl Uniforms {
             current_state: current_state.min(5),
             target_state: target_state.min(5),
             frame_count,
-            _pad0: 0,
+            seed: 0,
             resolution: [width as f32, height as f32],
             position,
             damping: damping.clamp(0.95, 0.99999),
@@ -75,9 +176,101 @@ impl Uniforms {
             trail_fade: trail_fade.clamp(0.9, 0.99999),
             glow_intensity: glow_intensity.clamp(0.0, 4.0),
             color_shift: color_shift.clamp(-1.0, 1.0),
-            _pad1: 0.0,
+            transform: 0,
+            mods: [0.55, 0.9, 0.35, 1.0],
+            force_gain: [1.0, 1.0],
+            delta_time: SIM_STEP.as_secs_f32(),
+            trail_steps: 4,
+            palette_frequency: [2.0, 3.4, 1.2, 0.7],
+            palette_phase: [0.8, 0.0, 1.2, 0.3],
+            viewport: FULL_VIEWPORT,
+            particle_count: 0,
+            _pad: [0; 3],
+            flock_radius: [0.0; 4],
+            flock_weight: [0.0; 4],
         }
     }
+
+    pub fn with_swarm(mut self, mods: [f32; 4], force_gain: [f32; 2]) -> Self {
+        self.mods = mods;
+        self.force_gain = force_gain;
+        self
+    }
+
+    /// Turns on the boids forces with the given radii and weights, each in separation,
+    /// alignment, cohesion order.
+    pub fn with_flock(mut self, radius: [f32; 3], weight: [f32; 3]) -> Self {
+        let radius = radius.map(|r| r.clamp(0.02, 0.5));
+        let largest = radius.into_iter().fold(0.0, f32::max);
+        self.flock_radius = [radius[0], radius[1], radius[2], largest];
+        self.flock_weight = [weight[0], weight[1], weight[2], 0.0];
+        self
+    }
+
+    /// Whether any boids force is active.
+    pub fn flocking(&self) -> bool {
+        self.flock_weight.iter().any(|&w| w != 0.0)
+    }
+
+    pub fn with_palette(
+        mut self,
+        frequency: [f32; 3],
+        phase: [f32; 3],
+        amplitude: f32,
+        offset: f32,
+    ) -> Self {
+        self.palette_frequency = [frequency[0], frequency[1], frequency[2], amplitude];
+        self.palette_phase = [phase[0], phase[1], phase[2], offset];
+        self
+    }
+
+    /// The same frame as seen by one output: its own size, frame counter and viewport.
+    pub fn for_output(
+        mut self,
+        width: u32,
+        height: u32,
+        frame_count: u32,
+        viewport: [f32; 4],
+    ) -> Self {
+        self.resolution = [width as f32, height as f32];
+        self.frame_count = frame_count;
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_trail_steps(mut self, trail_steps: u32) -> Self {
+        self.trail_steps = trail_steps;
+        self
+    }
+
+    pub fn with_delta_time(mut self, delta_time: f32) -> Self {
+        self.delta_time = delta_time;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: u32) -> Self {
+        self.transform = transform;
+        self
+    }
+}
+
+/// Tightly packed 8-bit RGBA pixels, top row first.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// Matches what an sRGB swapchain shows for a linear render texture value.
+fn linear_to_srgb8(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let encoded = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0 + 0.5) as u8
 }
 
 #[derive(Debug)]
@@ -100,7 +293,9 @@ impl PingPongTexture {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -108,10 +303,76 @@ impl PingPongTexture {
     }
 }
 
-fn create_simulation_bind_group(
+fn uniform_layout_entry(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: Some(
+                NonZeroU64::new(std::mem::size_of::<Uniforms>() as u64).unwrap(),
+            ),
+        },
+        count: None,
+    }
+}
+
+fn texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn storage_layout_entry(binding: u32, read_only: bool, size: u64) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: Some(NonZeroU64::new(size).unwrap()),
+        },
+        count: None,
+    }
+}
+
+fn create_uniforms(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
-    prev_state: &wgpu::TextureView,
+    label: &str,
+) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: std::mem::size_of::<Uniforms>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    });
+    (buffer, bind_group)
+}
+
+fn create_texture_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
     label: &str,
 ) -> wgpu::BindGroup {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -119,16 +380,16 @@ fn create_simulation_bind_group(
         layout,
         entries: &[wgpu::BindGroupEntry {
             binding: 0,
-            resource: wgpu::BindingResource::TextureView(prev_state),
+            resource: wgpu::BindingResource::TextureView(view),
         }],
     })
 }
 
-fn create_render_bind_group(
+fn create_present_bind_group(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
-    state: &wgpu::TextureView,
-    prev_render: &wgpu::TextureView,
+    render: &wgpu::TextureView,
+    uniform_buffer: &wgpu::Buffer,
     label: &str,
 ) -> wgpu::BindGroup {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -137,149 +398,453 @@ fn create_render_bind_group(
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::TextureView(state),
+                resource: wgpu::BindingResource::TextureView(render),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::TextureView(prev_render),
+                resource: uniform_buffer.as_entire_binding(),
             },
         ],
     })
 }
 
-fn create_present_bind_group(
+/// Where the present pass writes: a Wayland swapchain, or a plain texture when running
+/// without a compositor.
+enum Target {
+    Surface {
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration,
+    },
+    Offscreen {
+        texture: wgpu::Texture,
+    },
+}
+
+impl Target {
+    fn size(&self) -> (u32, u32) {
+        match self {
+            Target::Surface { config, .. } => (config.width, config.height),
+            Target::Offscreen { texture, .. } => (texture.width(), texture.height()),
+        }
+    }
+}
+
+fn create_offscreen_target(device: &wgpu::Device, width: u32, height: u32) -> Target {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Sentinel Offscreen Target"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OFFSCREEN_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    Target::Offscreen { texture }
+}
+
+/// The passes, each with its own shader and pipeline. Each [`Backend`] uses some of them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShaderStage {
+    Simulation,
+    Render,
+    Present,
+    Particles,
+    Sprites,
+    Composite,
+}
+
+impl ShaderStage {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Simulation => "simulation.wgsl",
+            Self::Render => "render.wgsl",
+            Self::Present => "entity.wgsl",
+            Self::Particles => "particles.wgsl",
+            Self::Sprites => "sprites.wgsl",
+            Self::Composite => "composite.wgsl",
+        }
+    }
+
+    fn builtin_source(self) -> &'static str {
+        match self {
+            Self::Simulation => include_str!("shaders/simulation.wgsl"),
+            Self::Render => include_str!("shaders/render.wgsl"),
+            Self::Present => include_str!("shaders/entity.wgsl"),
+            Self::Particles => include_str!("shaders/particles.wgsl"),
+            Self::Sprites => include_str!("shaders/sprites.wgsl"),
+            Self::Composite => include_str!("shaders/composite.wgsl"),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Simulation => "Simulation",
+            Self::Render => "Render",
+            Self::Present => "Present",
+            Self::Particles => "Particles",
+            Self::Sprites => "Sprites",
+            Self::Composite => "Composite",
+        }
+    }
+}
+
+/// Parses and validates WGSL with naga so errors can be reported with source locations
+/// instead of surfacing as a device error.
+fn validate_wgsl(source: &str, path: &str) -> anyhow::Result<()> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|err| anyhow::anyhow!("{}", err.emit_to_string_with_path(source, path)))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    )
+    .validate(&module)
+    .map_err(|err| anyhow::anyhow!("{}", err.emit_to_string_with_path(source, path)))?;
+    Ok(())
+}
+
+/// Builds the pipeline of a render `stage` from `source`, using the fragment shader
+/// `fs_entry`.
+fn create_pipeline(
     device: &wgpu::Device,
-    layout: &wgpu::BindGroupLayout,
-    render: &wgpu::TextureView,
-    label: &str,
-) -> wgpu::BindGroup {
-    device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some(label),
-        layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: wgpu::BindingResource::TextureView(render),
-        }],
+    stage: ShaderStage,
+    layout: &wgpu::PipelineLayout,
+    source: &str,
+    fs_entry: &str,
+    present_format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(&format!("Sentinel {} Shader", stage.label())),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let target = match stage {
+        // Rgba32Float doesn't support blending. The statistics pass writes the same format.
+        ShaderStage::Simulation => wgpu::ColorTargetState {
+            format: STATE_TEXTURE_FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        },
+        ShaderStage::Render | ShaderStage::Composite => wgpu::ColorTargetState {
+            format: RENDER_TEXTURE_FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        },
+        ShaderStage::Present => wgpu::ColorTargetState {
+            format: present_format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        },
+        // Sprites add up their light.
+        ShaderStage::Sprites => wgpu::ColorTargetState {
+            format: LIGHT_TEXTURE_FORMAT,
+            blend: Some(wgpu::BlendState {
+                color: ADDITIVE_BLEND,
+                alpha: ADDITIVE_BLEND,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+        },
+        ShaderStage::Particles => unreachable!("the particle stage is a compute pipeline"),
+    };
+    // Sprites are one quad per particle, read straight from the particle buffer.
+    let (buffers, topology): (&[wgpu::VertexBufferLayout], _) = match stage {
+        ShaderStage::Sprites => (
+            &[wgpu::VertexBufferLayout {
+                array_stride: PARTICLE_SIZE,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4],
+            }],
+            wgpu::PrimitiveTopology::TriangleStrip,
+        ),
+        _ => (&[], wgpu::PrimitiveTopology::TriangleList),
+    };
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("Sentinel {} Pipeline", stage.label())),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: fs_entry,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(target)],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
     })
 }
 
-fn create_render_targets(
+/// The entry points of the particle stage, in dispatch order.
+struct ParticlePipelines {
+    /// `cs_stats`
+    stats: wgpu::ComputePipeline,
+    /// `cs_clear_grid` and `cs_bin`, only dispatched while flocking.
+    clear_grid: wgpu::ComputePipeline,
+    bin: wgpu::ComputePipeline,
+    /// `cs_main`
+    simulate: wgpu::ComputePipeline,
+}
+
+/// Builds all pipelines of the particle stage from `source`.
+fn create_compute_pipelines(
     device: &wgpu::Device,
-    render_layout: &wgpu::BindGroupLayout,
-    present_layout: &wgpu::BindGroupLayout,
-    state_textures: &[PingPongTexture; 2],
-    size: wgpu::Extent3d,
-) -> (
-    [PingPongTexture; 2],
-    [wgpu::BindGroup; 2],
-    [wgpu::BindGroup; 2],
-) {
-    let render_textures = std::array::from_fn(|index| {
-        PingPongTexture::new(
-            device,
-            size,
-            RENDER_TEXTURE_FORMAT,
-            &format!("Sentinel Render Texture {index}"),
-        )
+    layout: &wgpu::PipelineLayout,
+    source: &str,
+) -> ParticlePipelines {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Sentinel Particles Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
     });
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Sentinel Particles Pipeline"),
+            layout: Some(layout),
+            module: &shader,
+            entry_point,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        })
+    };
+    ParticlePipelines {
+        stats: pipeline("cs_stats"),
+        clear_grid: pipeline("cs_clear_grid"),
+        bin: pipeline("cs_bin"),
+        simulate: pipeline("cs_main"),
+    }
+}
 
-    let render_bind_groups = [
-        create_render_bind_group(
-            device,
-            render_layout,
-            &state_textures[0].view,
-            &render_textures[1].view,
-            "Sentinel Render Bind Group A",
-        ),
-        create_render_bind_group(
-            device,
-            render_layout,
-            &state_textures[1].view,
-            &render_textures[0].view,
-            "Sentinel Render Bind Group B",
-        ),
-    ];
+/// Starts a render pass that clears `view` to black and draws into it.
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    label: &str,
+    view: &'a wgpu::TextureView,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    })
+}
 
-    let present_bind_groups = [
-        create_present_bind_group(
-            device,
-            present_layout,
-            &render_textures[0].view,
-            "Sentinel Present Bind Group A",
-        ),
-        create_present_bind_group(
-            device,
-            present_layout,
-            &render_textures[1].view,
-            "Sentinel Present Bind Group B",
-        ),
-    ];
+fn log_adapter(adapter: &wgpu::Adapter) {
+    let adapter_info = adapter.get_info();
+    info!(
+        "GPU adapter: {} (vendor={:#06x} device={:#06x} type={:?} backend={:?})",
+        adapter_info.name,
+        adapter_info.vendor,
+        adapter_info.device,
+        adapter_info.device_type,
+        adapter_info.backend
+    );
+}
+
+/// Particle state for one swarm, ping-ponged between two copies, and the simulation pass that
+/// advances it. Any number of outputs can draw the same swarm.
+pub struct Swarm {
+    seed: u32,
+    particle_count: u32,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    particles: Particles,
+    /// Where the statistics of a step are copied to for reading on the CPU.
+    readback: wgpu::Buffer,
+    /// Reports when `readback` is mapped; `None` while no copy is in flight.
+    readback_pending: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+    stats: Option<SwarmStats>,
+    frame_index: u64,
+}
 
-    (render_textures, render_bind_groups, present_bind_groups)
+/// Where a swarm's particles live, depending on the [`Backend`].
+// There are only ever a few swarms, so the variants' sizes don't matter.
+#[allow(clippy::large_enum_variant)]
+enum Particles {
+    /// One column per particle, velocity in row 0 and position in row 100 (the other rows
+    /// are only written, never read). `bind_groups[i]` reads `textures[i]`; the statistics
+    /// pass writes a single texel of `stats`, which the simulation reads through
+    /// `stats_bind_group`.
+    Texture {
+        textures: [PingPongTexture; 2],
+        bind_groups: [wgpu::BindGroup; 2],
+        stats: PingPongTexture,
+        stats_bind_group: wgpu::BindGroup,
+    },
+    /// One `Particle` per swarm member. `bind_groups[i]` reads `buffers[1 - i]` and writes
+    /// `buffers[i]`, and binds `stats` for both the statistics and the simulation pass.
+    Buffer {
+        buffers: [wgpu::Buffer; 2],
+        bind_groups: [wgpu::BindGroup; 2],
+        stats: wgpu::Buffer,
+    },
 }
 
-pub struct GpuRenderer {
-    surface: wgpu::Surface<'static>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    simulation_pipeline: wgpu::RenderPipeline,
-    render_pipeline: wgpu::RenderPipeline,
-    present_pipeline: wgpu::RenderPipeline,
+/// Where a swarm actually is, measured on the GPU before a simulation step. Both values are
+/// in simulation space, where the anchor `position` (0..1 across the canvas) sits at
+/// `(position - 0.5) * (aspect, 1) * 2`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SwarmStats {
+    /// Centroid of all particles.
+    pub center: [f32; 3],
+    /// Root-mean-square distance of the particles from `center`.
+    pub spread: f32,
+}
+
+impl Swarm {
+    /// Number of simulation steps run so far, saturating.
+    pub fn step_count(&self) -> u32 {
+        u32::try_from(self.frame_index).unwrap_or(u32::MAX)
+    }
+
+    /// The most recent statistics read back from the GPU. They trail the simulation by a
+    /// frame or two, since reading them never waits for the GPU.
+    pub fn stats(&mut self) -> Option<SwarmStats> {
+        self.receive_stats();
+        self.stats
+    }
+
+    /// Takes the statistics out of `readback` if its mapping has finished.
+    fn receive_stats(&mut self) {
+        let Some(receiver) = &self.readback_pending else {
+            return;
+        };
+        match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return,
+            Ok(Ok(())) => {
+                let slice = self.readback.slice(..);
+                {
+                    let data = slice.get_mapped_range();
+                    let values: &[f32] = bytemuck::cast_slice(&data);
+                    self.stats = Some(SwarmStats {
+                        center: [values[0], values[1], values[2]],
+                        spread: values[3],
+                    });
+                }
+                self.readback.unmap();
+            }
+            // The mapping failed; try again with the next step.
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => {}
+        }
+        self.readback_pending = None;
+    }
+
+    /// Index of the state texture the last simulation step wrote.
+    fn latest(&self) -> usize {
+        (self.frame_index.wrapping_sub(1) % 2) as usize
+    }
+}
+
+/// One place the swarm is shown: a Wayland surface or an offscreen texture, with its own
+/// trail buffers sized to it.
+pub struct OutputTarget {
+    target: Target,
+    format: wgpu::TextureFormat,
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
-    simulation_texture_bind_group_layout: wgpu::BindGroupLayout,
-    render_texture_bind_group_layout: wgpu::BindGroupLayout,
-    present_texture_bind_group_layout: wgpu::BindGroupLayout,
-    state_textures: [PingPongTexture; 2],
     render_textures: [PingPongTexture; 2],
-    simulation_bind_groups: [wgpu::BindGroup; 2],
-    render_bind_groups: [wgpu::BindGroup; 2],
+    /// `trail_bind_groups[i]` reads the trail the frame before wrote, for drawing into
+    /// `render_textures[i]`.
+    trail_bind_groups: [wgpu::BindGroup; 2],
     present_bind_groups: [wgpu::BindGroup; 2],
+    /// The compute backend's sprite light, and the bind group the composite pass reads it
+    /// through.
+    light: Option<(PingPongTexture, wgpu::BindGroup)>,
     frame_index: u64,
 }
 
-impl GpuRenderer {
-    pub fn new(
-        display: NonNull<c_void>,
-        surface: NonNull<c_void>,
-        width: u32,
-        height: u32,
-    ) -> anyhow::Result<Self> {
+impl OutputTarget {
+    pub fn size(&self) -> (u32, u32) {
+        self.target.size()
+    }
+}
+
+/// The particle pipelines of the backend in use.
+enum Pipelines {
+    Fragment {
+        /// `fs_stats` from the simulation shader.
+        stats: wgpu::RenderPipeline,
+        simulation: wgpu::RenderPipeline,
+        render: wgpu::RenderPipeline,
+    },
+    Compute {
+        particles: ParticlePipelines,
+        sprites: wgpu::RenderPipeline,
+        composite: wgpu::RenderPipeline,
+    },
+}
+
+/// The device and everything that doesn't depend on a particular swarm or output: bind
+/// group layouts and pipelines.
+pub struct GpuContext {
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    /// Wayland display for creating surfaces; `None` when headless.
+    display: Option<NonNull<c_void>>,
+    backend: Backend,
+    uniform_layout: wgpu::BindGroupLayout,
+    /// A single unfilterable float texture: the simulation's previous state, and the render
+    /// pass's particle state (or sprite light) and previous trail.
+    texture_layout: wgpu::BindGroupLayout,
+    /// The compute backend's previous and next particle buffers.
+    particle_layout: wgpu::BindGroupLayout,
+    present_layout: wgpu::BindGroupLayout,
+    /// Indexed by [`ShaderStage`].
+    pipeline_layouts: [wgpu::PipelineLayout; 6],
+    /// The fragment backend's statistics pass, which reads the state but not the statistics.
+    stats_pipeline_layout: wgpu::PipelineLayout,
+    pipelines: Pipelines,
+    /// Present pipelines for each output format in use, all built from `present_source`.
+    present_pipelines: Vec<(wgpu::TextureFormat, wgpu::RenderPipeline)>,
+    present_source: String,
+    /// The directory [`Self::load_shader_dir`] loaded, whose present shader a removed custom
+    /// one gives way to.
+    shader_dir: Option<PathBuf>,
+    next_seed: u32,
+}
+
+impl GpuContext {
+    /// Creates a context for presenting to Wayland surfaces on `display`.
+    pub fn new_wayland(display: NonNull<c_void>, backend: Backend) -> anyhow::Result<Self> {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::VULKAN,
             ..Default::default()
         });
 
-        let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(display));
-        let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(surface));
-
-        // SAFETY:
-        // - The Wayland display + surface handles are valid objects coming from the Wayland connection.
-        // - The underlying wl_display and wl_surface outlive the renderer and its wgpu::Surface.
-        let surface: wgpu::Surface<'static> = unsafe {
-            instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
-                raw_display_handle,
-                raw_window_handle,
-            })?
-        };
-
+        // Outputs come and go, so there is no surface to match the adapter against yet;
+        // `create_surface_output` checks that the adapter can present to each one.
         let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
+            compatible_surface: None,
             force_fallback_adapter: false,
         }))
         .ok_or_else(|| anyhow::anyhow!("No suitable GPU adapter found"))?;
-
-        let adapter_info = adapter.get_info();
-        info!(
-            "GPU adapter: {} (vendor={:#06x} device={:#06x} type={:?} backend={:?})",
-            adapter_info.name,
-            adapter_info.vendor,
-            adapter_info.device,
-            adapter_info.device_type,
-            adapter_info.backend
-        );
+        log_adapter(&adapter);
 
         let (device, queue) = pollster::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
@@ -290,469 +855,1021 @@ impl GpuRenderer {
             None,
         ))?;
 
-        let caps = surface.get_capabilities(&adapter);
-        let format = caps
-            .formats
-            .iter()
-            .copied()
-            .find(wgpu::TextureFormat::is_srgb)
-            .unwrap_or(caps.formats[0]);
-        let alpha_mode = caps
-            .alpha_modes
-            .iter()
-            .copied()
-            .find(|m| *m == wgpu::CompositeAlphaMode::Opaque)
-            .unwrap_or(caps.alpha_modes[0]);
-
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format,
-            width: width.max(1),
-            height: height.max(1),
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode,
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        };
-        surface.configure(&device, &config);
-        info!(
-            "Surface configured: {}x{} format={:?} alpha_mode={:?}",
-            config.width, config.height, format, alpha_mode
-        );
-
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Sentinel Uniform Buffer"),
-            contents: bytemuck::bytes_of(&Uniforms::new(
-                0.0,
-                0,
-                0,
-                0.0,
-                0.0,
-                1.0,
-                [0.5, 0.5],
-                config.width,
-                config.height,
-                0,
-                0.998,
-                5.0,
-                0.5,
-                1.0,
-                0.995,
-                1.0,
-                0.0,
-            )),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Sentinel Uniform Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: Some(
-                            NonZeroU64::new(std::mem::size_of::<Uniforms>() as u64).unwrap(),
-                        ),
-                    },
-                    count: None,
-                }],
-            });
+        Ok(Self::with_device(
+            instance,
+            adapter,
+            device,
+            queue,
+            Some(display),
+            backend,
+        ))
+    }
 
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Sentinel Uniform Bind Group"),
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
+    /// Creates a context for offscreen rendering without a compositor. Any backend wgpu can
+    /// find is accepted (`WGPU_BACKEND` narrows the choice); with `software` set, or when no
+    /// hardware adapter exists, a fallback adapter such as llvmpipe/lavapipe is used.
+    pub fn new_headless(software: bool, backend: Backend) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()),
+            ..Default::default()
         });
 
-        let simulation_texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Sentinel Simulation Texture Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                }],
-            });
+        let request = |force_fallback_adapter| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter,
+            }))
+        };
+        let adapter = if software {
+            request(true)
+        } else {
+            request(false).or_else(|| {
+                info!("No hardware GPU adapter found; trying a fallback adapter");
+                request(true)
+            })
+        }
+        .ok_or_else(|| anyhow::anyhow!("No suitable GPU adapter found"))?;
+        log_adapter(&adapter);
 
-        let render_texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Sentinel Render Texture Bind Group Layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                ],
-            });
+        // Software and GL adapters often only meet the downlevel limits; the pipeline needs
+        // nothing beyond them apart from a texture large enough for the render targets.
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::empty(),
+                required_limits:
+                    wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            },
+            None,
+        ))?;
 
-        let present_texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Sentinel Present Texture Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                }],
-            });
+        Ok(Self::with_device(
+            instance, adapter, device, queue, None, backend,
+        ))
+    }
 
-        let simulation_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Sentinel Simulation Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/simulation.wgsl").into()),
+    fn with_device(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        display: Option<NonNull<c_void>>,
+        backend: Backend,
+    ) -> Self {
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sentinel Uniform Bind Group Layout"),
+            entries: &[uniform_layout_entry(
+                0,
+                wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+            )],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sentinel Texture Bind Group Layout"),
+            entries: &[texture_layout_entry(0)],
         });
-        let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Sentinel Render Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/render.wgsl").into()),
+        let particle_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sentinel Particle Bind Group Layout"),
+            entries: &[
+                storage_layout_entry(0, true, PARTICLE_SIZE),
+                storage_layout_entry(1, false, PARTICLE_SIZE),
+                storage_layout_entry(2, false, STATS_SIZE),
+                storage_layout_entry(3, false, 4),
+            ],
         });
-        let present_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Sentinel Present Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/entity.wgsl").into()),
+        // The uniforms aren't used by the built-in shader; they are part of the custom
+        // present shader contract documented in entity.wgsl.
+        let present_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sentinel Present Bind Group Layout"),
+            entries: &[
+                texture_layout_entry(0),
+                uniform_layout_entry(1, wgpu::ShaderStages::FRAGMENT),
+            ],
         });
 
+        // The simulation reads the previous state and the swarm statistics measured on it.
         let simulation_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Sentinel Simulation Pipeline Layout"),
-                bind_group_layouts: &[&uniform_bind_group_layout, &simulation_texture_bind_group_layout],
+                bind_group_layouts: &[&uniform_layout, &texture_layout, &texture_layout],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Sentinel Render Pipeline Layout"),
-            bind_group_layouts: &[&uniform_bind_group_layout, &render_texture_bind_group_layout],
-            push_constant_ranges: &[],
-        });
+        let stats_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sentinel Stats Pipeline Layout"),
+                bind_group_layouts: &[&uniform_layout, &texture_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sentinel Render Pipeline Layout"),
+                bind_group_layouts: &[&uniform_layout, &texture_layout, &texture_layout],
+                push_constant_ranges: &[],
+            });
 
         let present_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Sentinel Present Pipeline Layout"),
-                bind_group_layouts: &[&present_texture_bind_group_layout],
+                bind_group_layouts: &[&present_layout],
                 push_constant_ranges: &[],
             });
 
-        let simulation_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Sentinel Simulation Pipeline"),
-            layout: Some(&simulation_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &simulation_shader,
-                entry_point: "vs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &simulation_shader,
-                entry_point: "fs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: STATE_TEXTURE_FORMAT,
-                    blend: None, // Rgba32Float doesn't support blending
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+        let particles_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sentinel Particles Pipeline Layout"),
+                bind_group_layouts: &[&uniform_layout, &particle_layout],
+                push_constant_ranges: &[],
+            });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Sentinel Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &render_shader,
-                entry_point: "vs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &render_shader,
-                entry_point: "fs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: RENDER_TEXTURE_FORMAT,
-                    blend: None, // Rgba32Float doesn't support blending
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+        let sprites_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sentinel Sprites Pipeline Layout"),
+                bind_group_layouts: &[&uniform_layout],
+                push_constant_ranges: &[],
+            });
+
+        // The composite pass is bound like the render pass, with the sprite light in place of
+        // the particle state.
+        let composite_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sentinel Composite Pipeline Layout"),
+                bind_group_layouts: &[&uniform_layout, &texture_layout, &texture_layout],
+                push_constant_ranges: &[],
+            });
 
-        let present_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Sentinel Present Pipeline"),
-            layout: Some(&present_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &present_shader,
-                entry_point: "vs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[],
+        let pipeline_layouts = [
+            simulation_pipeline_layout,
+            render_pipeline_layout,
+            present_pipeline_layout,
+            particles_pipeline_layout,
+            sprites_pipeline_layout,
+            composite_pipeline_layout,
+        ];
+        let build = |stage: ShaderStage| {
+            create_pipeline(
+                &device,
+                stage,
+                &pipeline_layouts[stage as usize],
+                stage.builtin_source(),
+                "fs_main",
+                OFFSCREEN_TEXTURE_FORMAT,
+            )
+        };
+        let pipelines = match backend {
+            Backend::Fragment => Pipelines::Fragment {
+                stats: create_pipeline(
+                    &device,
+                    ShaderStage::Simulation,
+                    &stats_pipeline_layout,
+                    ShaderStage::Simulation.builtin_source(),
+                    "fs_stats",
+                    OFFSCREEN_TEXTURE_FORMAT,
+                ),
+                simulation: build(ShaderStage::Simulation),
+                render: build(ShaderStage::Render),
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &present_shader,
-                entry_point: "fs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
+            Backend::Compute => Pipelines::Compute {
+                particles: create_compute_pipelines(
+                    &device,
+                    &pipeline_layouts[ShaderStage::Particles as usize],
+                    ShaderStage::Particles.builtin_source(),
+                ),
+                sprites: build(ShaderStage::Sprites),
+                composite: build(ShaderStage::Composite),
             },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
+        };
+        info!("Particle backend: {backend:?}");
+
+        Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            display,
+            backend,
+            uniform_layout,
+            texture_layout,
+            particle_layout,
+            present_layout,
+            pipeline_layouts,
+            stats_pipeline_layout,
+            pipelines,
+            present_pipelines: Vec::new(),
+            present_source: ShaderStage::Present.builtin_source().to_string(),
+            shader_dir: None,
+            next_seed: 0,
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Largest width or height of an output's textures on this device.
+    pub fn max_texture_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// A new swarm of `particle_count` particles (at most [`Backend::max_particles`]). Each
+    /// one gets a different seed, so independent swarms don't start out as copies of each
+    /// other.
+    pub fn create_swarm(&mut self, particle_count: u32) -> Swarm {
+        let seed = self.next_seed;
+        self.next_seed = self.next_seed.wrapping_add(1);
+        let particle_count = particle_count.clamp(1, self.backend.max_particles());
+
+        let (uniform_buffer, uniform_bind_group) = create_uniforms(
+            &self.device,
+            &self.uniform_layout,
+            "Sentinel Simulation Uniforms",
+        );
+        let particles = match self.backend {
+            Backend::Fragment => self.create_state_textures(particle_count),
+            Backend::Compute => self.create_particle_buffers(particle_count),
+        };
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sentinel Stats Readback Buffer"),
+            size: STATS_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
+        Swarm {
+            seed,
+            particle_count,
+            uniform_buffer,
+            uniform_bind_group,
+            particles,
+            readback,
+            readback_pending: None,
+            stats: None,
+            frame_index: 0,
+        }
+    }
+
+    fn create_state_textures(&self, particle_count: u32) -> Particles {
         let state_size = wgpu::Extent3d {
-            width: STATE_TEXTURE_WIDTH,
+            width: particle_count,
             height: STATE_TEXTURE_HEIGHT,
             depth_or_array_layers: 1,
         };
-
-        let state_textures = std::array::from_fn(|index| {
+        let textures: [PingPongTexture; 2] = std::array::from_fn(|index| {
             PingPongTexture::new(
-                &device,
+                &self.device,
                 state_size,
                 STATE_TEXTURE_FORMAT,
                 &format!("Sentinel State Texture {index}"),
             )
         });
+        let bind_groups = std::array::from_fn(|index| {
+            create_texture_bind_group(
+                &self.device,
+                &self.texture_layout,
+                &textures[index].view,
+                &format!("Sentinel State Bind Group {index}"),
+            )
+        });
+        let stats = PingPongTexture::new(
+            &self.device,
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            STATE_TEXTURE_FORMAT,
+            "Sentinel Stats Texture",
+        );
+        let stats_bind_group = create_texture_bind_group(
+            &self.device,
+            &self.texture_layout,
+            &stats.view,
+            "Sentinel Stats Bind Group",
+        );
+        Particles::Texture {
+            textures,
+            bind_groups,
+            stats,
+            stats_bind_group,
+        }
+    }
 
-        let simulation_bind_groups = [
-            create_simulation_bind_group(
-                &device,
-                &simulation_texture_bind_group_layout,
-                &state_textures[1].view,
-                "Sentinel Simulation Bind Group A",
-            ),
-            create_simulation_bind_group(
-                &device,
-                &simulation_texture_bind_group_layout,
-                &state_textures[0].view,
-                "Sentinel Simulation Bind Group B",
-            ),
-        ];
+    fn create_particle_buffers(&self, particle_count: u32) -> Particles {
+        // Zeroed; the first simulation steps lay the swarm out.
+        let buffers: [wgpu::Buffer; 2] = std::array::from_fn(|index| {
+            self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("Sentinel Particle Buffer {index}")),
+                size: u64::from(particle_count) * PARTICLE_SIZE,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+                mapped_at_creation: false,
+            })
+        });
+        let stats = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sentinel Stats Buffer"),
+            size: STATS_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let grid = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sentinel Grid Buffer"),
+            size: (GRID_CELLS + GRID_CELLS * CELL_CAPACITY) * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let bind_groups = std::array::from_fn(|index| {
+            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("Sentinel Particle Bind Group {index}")),
+                layout: &self.particle_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffers[1 - index].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffers[index].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: stats.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: grid.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+        Particles::Buffer {
+            buffers,
+            bind_groups,
+            stats,
+        }
+    }
 
-        let render_size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
-            depth_or_array_layers: 1,
+    /// Creates an output presenting to a Wayland surface.
+    ///
+    /// # Safety
+    ///
+    /// `surface` must be a live `wl_surface` on the display this context was created with,
+    /// and must outlive the returned target.
+    pub unsafe fn create_surface_output(
+        &mut self,
+        surface: NonNull<c_void>,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<OutputTarget> {
+        let display = self
+            .display
+            .ok_or_else(|| anyhow::anyhow!("headless context cannot present to surfaces"))?;
+        let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(display));
+        let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(surface));
+
+        // SAFETY: the caller guarantees the surface is valid and outlives the target; the
+        // display outlives the context.
+        let surface: wgpu::Surface<'static> = unsafe {
+            self.instance
+                .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
+                    raw_display_handle,
+                    raw_window_handle,
+                })?
         };
 
-        let (render_textures, render_bind_groups, present_bind_groups) = create_render_targets(
-            &device,
-            &render_texture_bind_group_layout,
-            &present_texture_bind_group_layout,
-            &state_textures,
-            render_size,
+        let caps = surface.get_capabilities(&self.adapter);
+        if caps.formats.is_empty() {
+            anyhow::bail!("GPU adapter cannot present to this surface");
+        }
+        let format = caps
+            .formats
+            .iter()
+            .copied()
+            .find(wgpu::TextureFormat::is_srgb)
+            .unwrap_or(caps.formats[0]);
+        let alpha_mode = caps
+            .alpha_modes
+            .iter()
+            .copied()
+            .find(|m| *m == wgpu::CompositeAlphaMode::Opaque)
+            .unwrap_or(caps.alpha_modes[0]);
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        surface.configure(&self.device, &config);
+        info!(
+            "Surface configured: {}x{} format={:?} alpha_mode={:?}",
+            config.width, config.height, format, alpha_mode
         );
 
-        Ok(Self {
-            surface,
-            device,
-            queue,
-            config,
-            simulation_pipeline,
-            render_pipeline,
-            present_pipeline,
+        Ok(self.create_output(Target::Surface { surface, config }, format))
+    }
+
+    /// Creates an output rendering into an offscreen texture that can be read back.
+    pub fn create_offscreen_output(&mut self, width: u32, height: u32) -> OutputTarget {
+        let target = create_offscreen_target(&self.device, width, height);
+        info!(
+            "Offscreen target: {}x{} format={:?}",
+            width.max(1),
+            height.max(1),
+            OFFSCREEN_TEXTURE_FORMAT
+        );
+        self.create_output(target, OFFSCREEN_TEXTURE_FORMAT)
+    }
+
+    fn create_output(&mut self, target: Target, format: wgpu::TextureFormat) -> OutputTarget {
+        self.ensure_present_pipeline(format);
+        let (uniform_buffer, uniform_bind_group) = create_uniforms(
+            &self.device,
+            &self.uniform_layout,
+            "Sentinel Output Uniforms",
+        );
+        let (width, height) = target.size();
+        let (render_textures, trail_bind_groups, present_bind_groups) =
+            self.create_render_targets(&uniform_buffer, width, height);
+        let light = self.create_light(width, height);
+        OutputTarget {
+            target,
+            format,
             uniform_buffer,
             uniform_bind_group,
-            simulation_texture_bind_group_layout,
-            render_texture_bind_group_layout,
-            present_texture_bind_group_layout,
-            state_textures,
             render_textures,
-            simulation_bind_groups,
-            render_bind_groups,
+            trail_bind_groups,
             present_bind_groups,
+            light,
             frame_index: 0,
-        })
+        }
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        if width == 0 || height == 0 {
-            return;
+    fn create_render_targets(
+        &self,
+        uniform_buffer: &wgpu::Buffer,
+        width: u32,
+        height: u32,
+    ) -> (
+        [PingPongTexture; 2],
+        [wgpu::BindGroup; 2],
+        [wgpu::BindGroup; 2],
+    ) {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let render_textures: [PingPongTexture; 2] = std::array::from_fn(|index| {
+            PingPongTexture::new(
+                &self.device,
+                size,
+                RENDER_TEXTURE_FORMAT,
+                &format!("Sentinel Render Texture {index}"),
+            )
+        });
+        let trail_bind_groups = std::array::from_fn(|index| {
+            create_texture_bind_group(
+                &self.device,
+                &self.texture_layout,
+                &render_textures[1 - index].view,
+                &format!("Sentinel Trail Bind Group {index}"),
+            )
+        });
+        let present_bind_groups = std::array::from_fn(|index| {
+            create_present_bind_group(
+                &self.device,
+                &self.present_layout,
+                &render_textures[index].view,
+                uniform_buffer,
+                &format!("Sentinel Present Bind Group {index}"),
+            )
+        });
+        (render_textures, trail_bind_groups, present_bind_groups)
+    }
+
+    /// The compute backend's sprite light for an output of the given size.
+    fn create_light(&self, width: u32, height: u32) -> Option<(PingPongTexture, wgpu::BindGroup)> {
+        if self.backend != Backend::Compute {
+            return None;
         }
-        if self.config.width == width && self.config.height == height {
+        let texture = PingPongTexture::new(
+            &self.device,
+            wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            LIGHT_TEXTURE_FORMAT,
+            "Sentinel Light Texture",
+        );
+        let bind_group = create_texture_bind_group(
+            &self.device,
+            &self.texture_layout,
+            &texture.view,
+            "Sentinel Light Bind Group",
+        );
+        Some((texture, bind_group))
+    }
+
+    pub fn resize(&self, output: &mut OutputTarget, width: u32, height: u32) {
+        let width = width.max(1);
+        let height = height.max(1);
+
+        match &mut output.target {
+            Target::Surface { surface, config } => {
+                if config.width == width && config.height == height {
+                    return;
+                }
+                config.width = width;
+                config.height = height;
+                surface.configure(&self.device, config);
+                info!("Surface resized: {width}x{height}");
+            }
+            Target::Offscreen { .. } => {
+                output.target = create_offscreen_target(&self.device, width, height);
+            }
+        }
+
+        let (render_textures, trail_bind_groups, present_bind_groups) =
+            self.create_render_targets(&output.uniform_buffer, width, height);
+        output.render_textures = render_textures;
+        output.trail_bind_groups = trail_bind_groups;
+        output.present_bind_groups = present_bind_groups;
+        output.light = self.create_light(width, height);
+    }
+
+    fn ensure_present_pipeline(&mut self, format: wgpu::TextureFormat) {
+        if self.present_pipelines.iter().any(|(f, _)| *f == format) {
             return;
         }
+        let layout = &self.pipeline_layouts[ShaderStage::Present as usize];
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipeline = create_pipeline(
+            &self.device,
+            ShaderStage::Present,
+            layout,
+            &self.present_source,
+            "fs_main",
+            format,
+        );
+        let pipeline = match pollster::block_on(self.device.pop_error_scope()) {
+            None => pipeline,
+            Some(err) => {
+                error!(
+                    "Present shader does not work with {format:?}, using the built-in one: {err}"
+                );
+                create_pipeline(
+                    &self.device,
+                    ShaderStage::Present,
+                    layout,
+                    ShaderStage::Present.builtin_source(),
+                    "fs_main",
+                    format,
+                )
+            }
+        };
+        self.present_pipelines.push((format, pipeline));
+    }
 
-        self.config.width = width;
-        self.config.height = height;
-        self.surface.configure(&self.device, &self.config);
+    fn present_pipeline(&self, format: wgpu::TextureFormat) -> &wgpu::RenderPipeline {
+        self.present_pipelines
+            .iter()
+            .find(|(f, _)| *f == format)
+            .map(|(_, pipeline)| pipeline)
+            .expect("present pipeline is created with the output")
+    }
 
-        let render_size = wgpu::Extent3d {
-            width: self.config.width,
-            height: self.config.height,
-            depth_or_array_layers: 1,
+    /// Rebuilds one stage's pipeline from WGSL `source`. On any error the previous pipeline
+    /// stays in use; `path` is only used to label error locations.
+    pub fn reload_shader(
+        &mut self,
+        stage: ShaderStage,
+        source: &str,
+        path: &str,
+    ) -> anyhow::Result<()> {
+        if !self.backend.stages().contains(&stage) {
+            anyhow::bail!(
+                "{path}: the {:?} backend has no {} stage",
+                self.backend,
+                stage.label()
+            );
+        }
+        validate_wgsl(source, path)?;
+
+        // naga accepts shaders that still don't fit this pipeline (wrong bindings or entry
+        // points); catch those as errors instead of letting wgpu treat them as fatal.
+        let device = &self.device;
+        let layout = &self.pipeline_layouts[stage as usize];
+        let check = || match pollster::block_on(device.pop_error_scope()) {
+            Some(err) => Err(anyhow::anyhow!("{path}: {err}")),
+            None => Ok(()),
         };
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        match (stage, &mut self.pipelines) {
+            (ShaderStage::Present, _) => {
+                let pipelines = self
+                    .present_pipelines
+                    .iter()
+                    .map(|(format, _)| {
+                        let pipeline =
+                            create_pipeline(device, stage, layout, source, "fs_main", *format);
+                        (*format, pipeline)
+                    })
+                    .collect();
+                check()?;
+                self.present_pipelines = pipelines;
+                self.present_source = source.to_string();
+            }
+            // The simulation stage also holds the statistics pass.
+            (
+                ShaderStage::Simulation,
+                Pipelines::Fragment {
+                    stats, simulation, ..
+                },
+            ) => {
+                let format = OFFSCREEN_TEXTURE_FORMAT;
+                let stats_layout = &self.stats_pipeline_layout;
+                let new_stats =
+                    create_pipeline(device, stage, stats_layout, source, "fs_stats", format);
+                let new_simulation =
+                    create_pipeline(device, stage, layout, source, "fs_main", format);
+                check()?;
+                *stats = new_stats;
+                *simulation = new_simulation;
+            }
+            (ShaderStage::Particles, Pipelines::Compute { particles, .. }) => {
+                let pipelines = create_compute_pipelines(device, layout, source);
+                check()?;
+                *particles = pipelines;
+            }
+            (ShaderStage::Render, Pipelines::Fragment { render: slot, .. })
+            | (ShaderStage::Sprites, Pipelines::Compute { sprites: slot, .. })
+            | (
+                ShaderStage::Composite,
+                Pipelines::Compute {
+                    composite: slot, ..
+                },
+            ) => {
+                let pipeline = create_pipeline(
+                    device,
+                    stage,
+                    layout,
+                    source,
+                    "fs_main",
+                    OFFSCREEN_TEXTURE_FORMAT,
+                );
+                check()?;
+                *slot = pipeline;
+            }
+            _ => unreachable!("checked against the backend's stages"),
+        }
+        Ok(())
+    }
 
-        let (render_textures, render_bind_groups, present_bind_groups) = create_render_targets(
-            &self.device,
-            &self.render_texture_bind_group_layout,
-            &self.present_texture_bind_group_layout,
-            &self.state_textures,
-            render_size,
-        );
+    /// Goes back to the shader compiled into the binary.
+    pub fn reset_shader(&mut self, stage: ShaderStage) {
+        self.reload_shader(stage, stage.builtin_source(), "built-in")
+            .expect("built-in shaders are valid");
+    }
+
+    /// Reloads `stage` from a WGSL file; see [`Self::reload_shader`].
+    pub fn reload_shader_file(&mut self, stage: ShaderStage, path: &Path) -> anyhow::Result<()> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", path.display()))?;
+        self.reload_shader(stage, &source, &path.display().to_string())
+    }
+
+    /// Replaces the built-in shaders of the backend's stages with the ones in `dir`. Stages
+    /// whose file is missing or invalid keep the built-in shader.
+    pub fn load_shader_dir(&mut self, dir: &Path) {
+        self.shader_dir = Some(dir.to_path_buf());
+        for &stage in self.backend.stages() {
+            let path = dir.join(stage.file_name());
+            match self.reload_shader_file(stage, &path) {
+                Ok(()) => info!("Loaded {} shader from {}", stage.label(), path.display()),
+                Err(err) => error!("Keeping built-in {} shader: {err:#}", stage.label()),
+            }
+        }
+    }
 
-        self.render_textures = render_textures;
-        self.render_bind_groups = render_bind_groups;
-        self.present_bind_groups = present_bind_groups;
+    /// Switches the present pass to the custom shader at `path`, or back to the one from the
+    /// shader directory, if any, else the built-in one. A custom shader that fails to load is
+    /// logged and the current pipeline kept.
+    pub fn set_present_shader(&mut self, path: Option<&Path>) {
+        let Some(path) = path else {
+            let stage = ShaderStage::Present;
+            if let Some(path) = self
+                .shader_dir
+                .as_ref()
+                .map(|dir| dir.join(stage.file_name()))
+            {
+                match self.reload_shader_file(stage, &path) {
+                    Ok(()) => info!("Loaded {} shader from {}", stage.label(), path.display()),
+                    Err(err) => {
+                        error!("Using built-in {} shader: {err:#}", stage.label());
+                        self.reset_shader(stage);
+                    }
+                }
+            } else {
+                self.reset_shader(stage);
+            }
+            return;
+        };
+        match self.reload_shader_file(ShaderStage::Present, path) {
+            Ok(()) => info!("Using present shader {}", path.display()),
+            Err(err) => error!("Keeping previous present shader: {err:#}"),
+        }
     }
 
-    pub fn render(&mut self, uniforms: &Uniforms) -> anyhow::Result<()> {
+    /// Advances `swarm` by one simulation step, measuring its statistics first. The statistics
+    /// are copied back for [`Swarm::stats`] whenever the previous copy has been read.
+    pub fn simulate(&self, swarm: &mut Swarm, uniforms: &Uniforms) {
+        swarm.receive_stats();
+        let uniforms = Uniforms {
+            seed: swarm.seed,
+            particle_count: swarm.particle_count,
+            ..*uniforms
+        };
         self.queue
-            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(uniforms));
+            .write_buffer(&swarm.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
 
-        let frame = match self.surface.get_current_texture() {
-            Ok(frame) => frame,
-            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
-                self.surface.configure(&self.device, &self.config);
-                return Ok(());
+        let write_index = (swarm.frame_index % 2) as usize;
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Sentinel Simulation Encoder"),
+            });
+        match (&self.pipelines, &swarm.particles) {
+            (
+                Pipelines::Fragment {
+                    stats: stats_pipeline,
+                    simulation,
+                    ..
+                },
+                Particles::Texture {
+                    textures,
+                    bind_groups,
+                    stats,
+                    stats_bind_group,
+                },
+            ) => {
+                {
+                    let mut pass = begin_pass(&mut encoder, "Sentinel Stats Pass", &stats.view);
+                    pass.set_pipeline(stats_pipeline);
+                    pass.set_bind_group(0, &swarm.uniform_bind_group, &[]);
+                    pass.set_bind_group(1, &bind_groups[1 - write_index], &[]);
+                    pass.draw(0..3, 0..1);
+                }
+                let mut pass = begin_pass(
+                    &mut encoder,
+                    "Sentinel Simulation Pass",
+                    &textures[write_index].view,
+                );
+                pass.set_pipeline(simulation);
+                pass.set_bind_group(0, &swarm.uniform_bind_group, &[]);
+                pass.set_bind_group(1, &bind_groups[1 - write_index], &[]);
+                pass.set_bind_group(2, stats_bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+            (Pipelines::Compute { particles, .. }, Particles::Buffer { bind_groups, .. }) => {
+                let workgroups = swarm.particle_count.div_ceil(PARTICLE_WORKGROUP_SIZE);
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Sentinel Particles Pass"),
+                    timestamp_writes: None,
+                });
+                pass.set_bind_group(0, &swarm.uniform_bind_group, &[]);
+                pass.set_bind_group(1, &bind_groups[write_index], &[]);
+                // A single workgroup reduces the whole swarm.
+                pass.set_pipeline(&particles.stats);
+                pass.dispatch_workgroups(1, 1, 1);
+                if uniforms.flocking() {
+                    pass.set_pipeline(&particles.clear_grid);
+                    pass.dispatch_workgroups(
+                        (GRID_CELLS as u32).div_ceil(PARTICLE_WORKGROUP_SIZE),
+                        1,
+                        1,
+                    );
+                    pass.set_pipeline(&particles.bin);
+                    pass.dispatch_workgroups(workgroups, 1, 1);
+                }
+                pass.set_pipeline(&particles.simulate);
+                pass.dispatch_workgroups(workgroups, 1, 1);
             }
-            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
-            Err(wgpu::SurfaceError::OutOfMemory) => {
-                return Err(anyhow::anyhow!("GPU out of memory"));
+            _ => unreachable!("swarms are created for the context's backend"),
+        }
+
+        // Only one copy is in flight at a time; steps in between go unmeasured on the CPU.
+        let read_stats = swarm.readback_pending.is_none();
+        if read_stats {
+            match &swarm.particles {
+                Particles::Texture { stats, .. } => encoder.copy_texture_to_buffer(
+                    stats.texture.as_image_copy(),
+                    wgpu::ImageCopyBuffer {
+                        buffer: &swarm.readback,
+                        layout: wgpu::ImageDataLayout::default(),
+                    },
+                    stats.texture.size(),
+                ),
+                Particles::Buffer { stats, .. } => {
+                    encoder.copy_buffer_to_buffer(stats, 0, &swarm.readback, 0, STATS_SIZE)
+                }
             }
+        }
+        self.queue.submit(Some(encoder.finish()));
+        if read_stats {
+            let (sender, receiver) = mpsc::channel();
+            swarm
+                .readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            swarm.readback_pending = Some(receiver);
+        }
+        swarm.frame_index = swarm.frame_index.wrapping_add(1);
+    }
+
+    /// Draws `swarm`'s current state into `output`'s trails and presents them. `uniforms`
+    /// should come from [`Uniforms::for_output`]. Returns false if the surface had no image to
+    /// draw into, in which case nothing was presented (or committed).
+    pub fn render(
+        &self,
+        output: &mut OutputTarget,
+        swarm: &Swarm,
+        uniforms: &Uniforms,
+    ) -> anyhow::Result<bool> {
+        let uniforms = Uniforms {
+            particle_count: swarm.particle_count,
+            ..*uniforms
         };
+        self.queue
+            .write_buffer(&output.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
 
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let frame = match &output.target {
+            Target::Surface { surface, config } => match surface.get_current_texture() {
+                Ok(frame) => Some(frame),
+                Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                    surface.configure(&self.device, config);
+                    return Ok(false);
+                }
+                Err(wgpu::SurfaceError::Timeout) => return Ok(false),
+                Err(wgpu::SurfaceError::OutOfMemory) => {
+                    return Err(anyhow::anyhow!("GPU out of memory"));
+                }
+            },
+            Target::Offscreen { .. } => None,
+        };
 
-        let write_index = (self.frame_index % 2) as usize;
-        let state_view = &self.state_textures[write_index].view;
-        let render_view = &self.render_textures[write_index].view;
+        let target_texture = match (&frame, &output.target) {
+            (Some(frame), _) => &frame.texture,
+            (None, Target::Offscreen { texture }) => texture,
+            (None, Target::Surface { .. }) => return Ok(false),
+        };
+        let view = target_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
+        let write_index = (output.frame_index % 2) as usize;
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Sentinel Render Encoder"),
             });
 
-        {
-            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Sentinel Simulation Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: state_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-            pass.set_pipeline(&self.simulation_pipeline);
-            pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            pass.set_bind_group(1, &self.simulation_bind_groups[write_index], &[]);
-            pass.draw(0..3, 0..1);
+        let trail_view = &output.render_textures[write_index].view;
+        match (&self.pipelines, &swarm.particles) {
+            (Pipelines::Fragment { render, .. }, Particles::Texture { bind_groups, .. }) => {
+                let mut pass = begin_pass(&mut encoder, "Sentinel Render Pass", trail_view);
+                pass.set_pipeline(render);
+                pass.set_bind_group(0, &output.uniform_bind_group, &[]);
+                pass.set_bind_group(1, &bind_groups[swarm.latest()], &[]);
+                pass.set_bind_group(2, &output.trail_bind_groups[write_index], &[]);
+                pass.draw(0..3, 0..1);
+            }
+            (
+                Pipelines::Compute {
+                    sprites, composite, ..
+                },
+                Particles::Buffer { buffers, .. },
+            ) => {
+                let (light, light_bind_group) = output
+                    .light
+                    .as_ref()
+                    .expect("compute backend outputs have a light texture");
+                {
+                    let mut pass = begin_pass(&mut encoder, "Sentinel Sprites Pass", &light.view);
+                    pass.set_pipeline(sprites);
+                    pass.set_bind_group(0, &output.uniform_bind_group, &[]);
+                    pass.set_vertex_buffer(0, buffers[swarm.latest()].slice(..));
+                    pass.draw(0..4, 0..swarm.particle_count);
+                }
+                let mut pass = begin_pass(&mut encoder, "Sentinel Composite Pass", trail_view);
+                pass.set_pipeline(composite);
+                pass.set_bind_group(0, &output.uniform_bind_group, &[]);
+                pass.set_bind_group(1, light_bind_group, &[]);
+                pass.set_bind_group(2, &output.trail_bind_groups[write_index], &[]);
+                pass.draw(0..3, 0..1);
+            }
+            _ => unreachable!("swarms are created for the context's backend"),
         }
 
         {
-            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Sentinel Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: render_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-            pass.set_pipeline(&self.render_pipeline);
-            pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            pass.set_bind_group(1, &self.render_bind_groups[write_index], &[]);
+            let mut pass = begin_pass(&mut encoder, "Sentinel Present Pass", &view);
+            pass.set_pipeline(self.present_pipeline(output.format));
+            pass.set_bind_group(0, &output.present_bind_groups[write_index], &[]);
             pass.draw(0..3, 0..1);
         }
 
-        {
-            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Sentinel Present Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-            pass.set_pipeline(&self.present_pipeline);
-            pass.set_bind_group(0, &self.present_bind_groups[write_index], &[]);
-            pass.draw(0..3, 0..1);
+        self.queue.submit(Some(encoder.finish()));
+        if let Some(frame) = frame {
+            frame.present();
         }
+        self.device.poll(wgpu::Maintain::Poll);
+        output.frame_index = output.frame_index.wrapping_add(1);
 
+        Ok(true)
+    }
+
+    /// Reads back `output`'s most recently rendered frame (the render texture the present
+    /// pass sampled) as 8-bit sRGB RGBA. Blocks until the GPU has finished the frame.
+    ///
+    /// The present pass itself is not captured: surface textures are only render targets
+    /// and are handed back to the compositor once presented, and reading the trail buffer
+    /// keeps captures and the golden images independent of the present shader.
+    pub fn read_frame(&self, output: &OutputTarget) -> anyhow::Result<Image> {
+        let read_index = (output.frame_index.wrapping_sub(1) % 2) as usize;
+        let texture = &output.render_textures[read_index].texture;
+        let (width, height) = (texture.width(), texture.height());
+
+        let bytes_per_pixel = RENDER_TEXTURE_FORMAT
+            .block_copy_size(None)
+            .expect("render texture format has a fixed block size");
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sentinel Readback Buffer"),
+            size: u64::from(bytes_per_row) * u64::from(height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Sentinel Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
         self.queue.submit(Some(encoder.finish()));
-        frame.present();
-        self.device.poll(wgpu::Maintain::Poll);
-        self.frame_index = self.frame_index.wrapping_add(1);
 
-        Ok(())
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks_exact(bytes_per_row as usize) {
+                let texels: &[f32] = bytemuck::cast_slice(&row[..unpadded_bytes_per_row as usize]);
+                for texel in texels.chunks_exact(4) {
+                    rgba.extend_from_slice(&[
+                        linear_to_srgb8(texel[0]),
+                        linear_to_srgb8(texel[1]),
+                        linear_to_srgb8(texel[2]),
+                        255,
+                    ]);
+                }
+            }
+        }
+        buffer.unmap();
+
+        Ok(Image {
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    /// Blocks until all submitted GPU work has finished.
+    pub fn wait_idle(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
     }
 }
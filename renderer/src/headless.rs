@@ -0,0 +1,115 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::{
+    capture::{self, FrameCapture, SequenceOptions},
+    cli::HeadlessOptions,
+    clock::SIM_STEP,
+    config::Config,
+    entity::Entity,
+    gpu::{GpuContext, OutputTarget, Swarm, FULL_VIEWPORT},
+};
+
+/// Simulated time between headless frames, matching a 60 Hz display: one simulation step
+/// per frame.
+const FRAME_INTERVAL: Duration = SIM_STEP;
+
+/// Steps the full simulation/render/present pipeline `options.frames` times into an
+/// offscreen texture. Animation time advances by a fixed interval per frame, so runs are
+/// independent of how fast the adapter actually is.
+pub fn run(
+    options: &HeadlessOptions,
+    config: &Config,
+    sequence: Option<SequenceOptions>,
+    shader_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    let mut gpu = GpuContext::new_headless(options.software, config.particles.backend)?;
+    let max_size = gpu.max_texture_size();
+    if options.width > max_size || options.height > max_size {
+        anyhow::bail!(
+            "--size {}x{} is larger than this GPU adapter supports ({max_size}x{max_size})",
+            options.width,
+            options.height
+        );
+    }
+    if let Some(dir) = shader_dir {
+        gpu.load_shader_dir(dir);
+    }
+    if let Some(path) = &config.present_shader {
+        gpu.set_present_shader(Some(path));
+    }
+    let mut swarm = gpu.create_swarm(config.particles.count);
+    let mut output = gpu.create_offscreen_output(options.width, options.height);
+    let mut frame_capture = FrameCapture::new(sequence)?;
+
+    let start_time = Instant::now();
+    let mut entity = Entity::from_config(config, start_time);
+
+    let wall_start = Instant::now();
+    render_frames(
+        &gpu,
+        &mut swarm,
+        &mut output,
+        &mut entity,
+        start_time,
+        options.frames,
+        |gpu, output, frame_count| {
+            frame_capture.after_frame(gpu, &[("offscreen", output)], frame_count);
+        },
+    )?;
+
+    if let Some(path) = &options.screenshot {
+        capture::write_png(path, &gpu.read_frame(&output)?)?;
+        info!("Wrote final frame to {}", path.display());
+    }
+
+    let wall = wall_start.elapsed();
+    info!(
+        "Headless run finished: {} frames at {}x{} in {:.2}s ({:.1} fps)",
+        options.frames,
+        options.width,
+        options.height,
+        wall.as_secs_f32(),
+        options.frames as f32 / wall.as_secs_f32().max(f32::EPSILON)
+    );
+    Ok(())
+}
+
+/// Renders `frames` frames of `entity` with animation time advancing by `FRAME_INTERVAL`
+/// from `start_time`, calling `after_frame` after each one. Waits for the GPU after every
+/// frame, so the swarm statistics fed back to the entity arrive at the same frame in every
+/// run.
+pub fn render_frames(
+    gpu: &GpuContext,
+    swarm: &mut Swarm,
+    output: &mut OutputTarget,
+    entity: &mut Entity,
+    start_time: Instant,
+    frames: u32,
+    mut after_frame: impl FnMut(&GpuContext, &OutputTarget, u32),
+) -> anyhow::Result<()> {
+    let (width, height) = output.size();
+    for frame_count in 0..frames {
+        if let Some(stats) = swarm.stats() {
+            entity.set_swarm_stats(stats);
+        }
+        let elapsed = FRAME_INTERVAL * frame_count;
+        let uniforms = entity.frame_uniforms(
+            start_time + elapsed,
+            elapsed.as_secs_f32(),
+            width,
+            height,
+            frame_count,
+        );
+        gpu.simulate(swarm, &uniforms);
+        let uniforms = uniforms.for_output(width, height, frame_count, FULL_VIEWPORT);
+        gpu.render(output, swarm, &uniforms)?;
+        after_frame(gpu, output, frame_count);
+        gpu.wait_idle();
+    }
+    Ok(())
+}
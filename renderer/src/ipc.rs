@@ -1,13 +1,30 @@
 use std::{
     env,
+    io::{self, Write},
     os::unix::net::UnixStream,
     path::{Path, PathBuf},
 };
 
 use log::{debug, warn};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+/// Highest version of the socket protocol the renderer speaks, sent in `hello`. Both sides use
+/// the lower of their two versions.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest version the renderer still speaks; a peer offering less is turned away.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// The `type`s of [`IpcMessage`], reported in `hello`.
+pub const ACCEPTED_MESSAGES: &[&str] = &[
+    "hello", "state", "release", "gaze", "pulse", "pause", "resume", "capture", "error",
+];
+
+/// Longest `duration_ms` of a `state` override: a day.
+pub const MAX_DURATION_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Identifies a connection: the one to the observer or one of the control socket's.
+pub type PeerId = u64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum EntityState {
     Idle,
@@ -29,21 +46,259 @@ impl EntityState {
             EntityState::Sleepy => 5,
         }
     }
+
+    pub fn from_u32(state: u32) -> Self {
+        match state {
+            1 => EntityState::Curious,
+            2 => EntityState::Focused,
+            3 => EntityState::Amused,
+            4 => EntityState::Alert,
+            5 => EntityState::Sleepy,
+            _ => EntityState::Idle,
+        }
+    }
 }
 
+/// Messages the renderer accepts. Fields it doesn't know are ignored, so newer peers can add
+/// them without a version bump.
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 pub enum IpcMessage {
+    /// The peer's half of the handshake. Peers that never send one are taken to speak
+    /// version 1 at priority 0.
+    #[serde(rename = "hello")]
+    Hello {
+        version: u32,
+        /// Priority of the peer's `state` messages over other peers'.
+        #[serde(default)]
+        priority: i32,
+    },
+    /// The peer's claim on the state shown, kept until it sends `release` or disconnects;
+    /// see `control::StateClaims`.
     #[serde(rename = "state")]
-    State { state: EntityState, intensity: f32 },
+    State {
+        state: EntityState,
+        intensity: f32,
+        /// Makes this a temporary override that ends after this long, even if the peer
+        /// disconnects.
+        #[serde(default)]
+        duration_ms: Option<u64>,
+        /// Replaces the priority from the peer's `hello` for this message.
+        #[serde(default)]
+        priority: Option<i32>,
+        /// Leaves the state in place after the peer disconnects, until another replaces it.
+        #[serde(default)]
+        persist: bool,
+    },
+    /// Withdraws the peer's `state` claim, handing the swarm to the next one.
+    #[serde(rename = "release")]
+    Release,
+    /// Normalized point the swarm should drift toward; (0,0) is the screen center,
+    /// (-1,-1) top-left and (1,1) bottom-right.
+    #[serde(rename = "gaze")]
+    Gaze { x: f32, y: f32 },
+    /// A one-shot swell of the swarm, `strength` from 0 to 1.
+    #[serde(rename = "pulse")]
+    Pulse {
+        #[serde(default = "full_strength")]
+        strength: f32,
+    },
+    /// Freezes the animation on its current frame until `resume`, whoever sends it.
+    #[serde(rename = "pause")]
+    Pause,
+    #[serde(rename = "resume")]
+    Resume,
+    /// Write the next rendered frame as PNG to the file named `path` in the capture directory
+    /// (a timestamped file if omitted). Only a bare file name is accepted.
+    #[serde(rename = "capture")]
+    Capture {
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// The peer rejected one of the renderer's messages; never answered, to avoid loops.
+    #[serde(rename = "error")]
+    Error { code: String, message: String },
+}
+
+fn full_strength() -> f32 {
+    1.0
+}
+
+/// Messages the renderer sends back, one JSON object per line like the ones it receives.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum RendererMessage {
+    /// Sent once, right after connecting.
+    #[serde(rename = "hello")]
+    Hello {
+        version: u32,
+        /// Message types the renderer understands.
+        messages: &'static [&'static str],
+        outputs: Vec<OutputStatus>,
+    },
+    /// Sent periodically while connected.
+    #[serde(rename = "status")]
+    Status {
+        state: EntityState,
+        target_state: EntityState,
+        /// Progress of the transition from `state` to `target_state`, 0 to 1.
+        blend: f32,
+        intensity: f32,
+        /// Frames drawn per second since the previous status, on the fastest visible output.
+        fps: f32,
+        /// Whether the animation is suspended because no output is visible.
+        paused: bool,
+        /// Whether the animation is frozen by a `pause` message.
+        held: bool,
+        outputs: Vec<OutputStatus>,
+    },
+    /// Reply to a message the renderer could not act on.
+    #[serde(rename = "error")]
+    Error {
+        code: ErrorCode,
+        message: String,
+        /// `type` of the rejected message, if it had one.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_type: Option<String>,
+    },
+}
+
+/// Why a message was rejected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Not JSON, no `type`, or a known type with missing or invalid fields.
+    InvalidMessage,
+    /// A `type` this version of the renderer doesn't know.
+    UnknownType,
+    /// A `hello` with a version older than [`MIN_PROTOCOL_VERSION`].
+    UnsupportedVersion,
+}
+
+/// A rejected message, to be reported back to the peer.
+#[derive(Debug)]
+pub struct IpcError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub request_type: Option<String>,
+}
+
+impl IpcError {
+    fn new(code: ErrorCode, message: String, request_type: Option<&str>) -> Self {
+        Self {
+            code,
+            message,
+            request_type: request_type.map(str::to_string),
+        }
+    }
+}
+
+impl From<IpcError> for RendererMessage {
+    fn from(err: IpcError) -> Self {
+        RendererMessage::Error {
+            code: err.code,
+            message: err.message,
+            request_type: err.request_type,
+        }
+    }
+}
+
+/// An output the renderer draws on, with the pixel size it renders at.
+#[derive(Debug, Serialize)]
+pub struct OutputStatus {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What the renderer keeps per connection.
+pub struct Peer {
+    /// Shown in logs: the observer's socket path or `control client <id>`.
+    pub name: String,
+    /// Received bytes not yet forming a complete line.
+    pub buffer: Vec<u8>,
+    /// `None` once a write failed; the read side then notices the broken connection.
+    pub writer: Option<IpcWriter>,
+    /// Priority of the peer's `state` claims, from its `hello`.
+    pub priority: i32,
+}
+
+impl Peer {
+    pub fn new(name: String, writer: IpcWriter) -> Self {
+        Self {
+            name,
+            buffer: Vec::new(),
+            writer: Some(writer),
+            priority: 0,
+        }
+    }
+
+    /// Sends `message`, if the connection still takes writes.
+    pub fn send(&mut self, message: &RendererMessage) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        if let Err(err) = writer.send(message) {
+            warn!("IPC write to {} failed: {err}", self.name);
+            self.writer = None;
+        }
+    }
+}
+
+/// The sending half of the connection. Writes never block the event loop: what the socket
+/// doesn't take right away is kept and goes out before the next message.
+pub struct IpcWriter {
+    stream: UnixStream,
+    pending: Vec<u8>,
+}
+
+impl IpcWriter {
+    pub fn new(stream: &UnixStream) -> io::Result<Self> {
+        Ok(Self {
+            stream: stream.try_clone()?,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Writes `message`, or as much of it as the socket takes. An error means the connection
+    /// is gone.
+    pub fn send(&mut self, message: &RendererMessage) -> io::Result<()> {
+        const MAX_PENDING_BYTES: usize = 64 * 1024;
+        // A peer that doesn't read misses messages rather than growing the backlog.
+        if self.pending.len() < MAX_PENDING_BYTES {
+            serde_json::to_writer(&mut self.pending, message)
+                .expect("renderer messages serialize to JSON");
+            self.pending.push(b'\n');
+        } else {
+            debug!("IPC peer is not reading; dropping a message");
+        }
+
+        while !self.pending.is_empty() {
+            match (&self.stream).write(&self.pending) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.pending.drain(..n);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
 }
 
-pub fn socket_candidates() -> Vec<PathBuf> {
+/// Socket paths to try, in order: `SENTINEL_SOCKET_PATH`, then the configured path, then
+/// the runtime-dir and `/tmp` defaults.
+pub fn socket_candidates(configured: Option<&Path>) -> Vec<PathBuf> {
     if let Ok(path) = env::var("SENTINEL_SOCKET_PATH") {
         if !path.trim().is_empty() {
             return vec![PathBuf::from(path)];
         }
     }
+    if let Some(path) = configured {
+        return vec![path.to_path_buf()];
+    }
 
     let mut candidates = Vec::new();
     if let Ok(dir) = env::var("XDG_RUNTIME_DIR") {
@@ -71,7 +326,74 @@ fn connect_one(path: &Path) -> std::io::Result<UnixStream> {
     Ok(stream)
 }
 
-pub fn drain_messages(buffer: &mut Vec<u8>) -> Vec<IpcMessage> {
+/// Parses one line: unknown types, malformed messages and a `hello` from a peer too old to
+/// talk to become [`IpcError`]s.
+fn parse_message(line: &str) -> Result<IpcMessage, IpcError> {
+    let value: serde_json::Value = serde_json::from_str(line).map_err(|err| {
+        IpcError::new(
+            ErrorCode::InvalidMessage,
+            format!("invalid JSON: {err}"),
+            None,
+        )
+    })?;
+    let Some(message_type) = value.get("type").and_then(|t| t.as_str()) else {
+        return Err(IpcError::new(
+            ErrorCode::InvalidMessage,
+            "missing `type`".to_string(),
+            None,
+        ));
+    };
+    if !ACCEPTED_MESSAGES.contains(&message_type) {
+        return Err(IpcError::new(
+            ErrorCode::UnknownType,
+            format!("unknown message type `{message_type}`"),
+            Some(message_type),
+        ));
+    }
+    let message_type = message_type.to_string();
+    let invalid = |message: String| {
+        IpcError::new(
+            ErrorCode::InvalidMessage,
+            format!("invalid `{message_type}` message: {message}"),
+            Some(&message_type),
+        )
+    };
+    let message = IpcMessage::deserialize(value).map_err(|err| invalid(err.to_string()))?;
+    match message {
+        IpcMessage::State {
+            duration_ms: Some(duration_ms),
+            ..
+        } if !(1..=MAX_DURATION_MS).contains(&duration_ms) => Err(invalid(format!(
+            "`duration_ms` must be between 1 and {MAX_DURATION_MS}, got {duration_ms}"
+        ))),
+        IpcMessage::Capture {
+            path: Some(ref path),
+        } if !is_file_name(path) => Err(invalid(format!(
+            "`path` must be a file name without directories, got {:?}",
+            path.display()
+        ))),
+        IpcMessage::Hello { version, .. } if version < MIN_PROTOCOL_VERSION => Err(IpcError::new(
+            ErrorCode::UnsupportedVersion,
+            format!(
+                "protocol version {version} is not supported, need at least \
+                     {MIN_PROTOCOL_VERSION}"
+            ),
+            Some("hello"),
+        )),
+        message => Ok(message),
+    }
+}
+
+/// Whether `path` is a single file name: no root, no directories, no `.` or `..`.
+fn is_file_name(path: &Path) -> bool {
+    let mut components = path.components();
+    matches!(components.next(), Some(std::path::Component::Normal(_)))
+        && components.next().is_none()
+}
+
+/// Takes the complete lines out of `buffer` and parses them, leaving a partial last line for
+/// the next read.
+pub fn drain_messages(buffer: &mut Vec<u8>) -> Vec<Result<IpcMessage, IpcError>> {
     const MAX_BUFFER_BYTES: usize = 1024 * 1024;
     if buffer.len() > MAX_BUFFER_BYTES {
         warn!("IPC buffer exceeded {MAX_BUFFER_BYTES} bytes; clearing");
@@ -79,12 +401,7 @@ pub fn drain_messages(buffer: &mut Vec<u8>) -> Vec<IpcMessage> {
     }
 
     let mut out = Vec::new();
-    loop {
-        let newline = match buffer.iter().position(|b| *b == b'\n') {
-            Some(idx) => idx,
-            None => break,
-        };
-
+    while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
         let mut line = buffer.drain(..=newline).collect::<Vec<u8>>();
         if line.last() == Some(&b'\n') {
             line.pop();
@@ -96,19 +413,156 @@ pub fn drain_messages(buffer: &mut Vec<u8>) -> Vec<IpcMessage> {
         let line = match std::str::from_utf8(&line) {
             Ok(s) => s.trim(),
             Err(err) => {
-                warn!("IPC message was not UTF-8: {err}");
+                out.push(Err(IpcError::new(
+                    ErrorCode::InvalidMessage,
+                    format!("not UTF-8: {err}"),
+                    None,
+                )));
                 continue;
             }
         };
         if line.is_empty() {
             continue;
         }
+        out.push(parse_message(line));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(line: &str) -> IpcError {
+        parse_message(line).expect_err("message should be rejected")
+    }
 
-        match serde_json::from_str::<IpcMessage>(line) {
-            Ok(msg) => out.push(msg),
-            Err(err) => warn!("IPC JSON parse failed: {err}; line={line:?}"),
+    #[test]
+    fn invalid_json() {
+        let err = rejected("{\"type\": \"state\"");
+        assert_eq!(err.code, ErrorCode::InvalidMessage);
+        assert!(err.message.starts_with("invalid JSON"), "{}", err.message);
+        assert_eq!(err.request_type, None);
+    }
+
+    #[test]
+    fn missing_type() {
+        for line in [r#"{"state":"idle"}"#, r#"{"type":3}"#, "[]"] {
+            let err = rejected(line);
+            assert_eq!(err.code, ErrorCode::InvalidMessage);
+            assert_eq!(err.message, "missing `type`");
         }
     }
 
-    out
+    #[test]
+    fn unknown_type() {
+        let err = rejected(r#"{"type":"dance"}"#);
+        assert_eq!(err.code, ErrorCode::UnknownType);
+        assert_eq!(err.request_type.as_deref(), Some("dance"));
+    }
+
+    #[test]
+    fn bad_fields() {
+        for line in [
+            r#"{"type":"state","intensity":0.5}"#,
+            r#"{"type":"state","state":"grumpy","intensity":0.5}"#,
+            r#"{"type":"gaze","x":"left","y":0}"#,
+            r#"{"type":"hello"}"#,
+        ] {
+            let err = rejected(line);
+            assert_eq!(err.code, ErrorCode::InvalidMessage, "{line}");
+            assert!(err.message.starts_with("invalid `"), "{}", err.message);
+            assert!(err.request_type.is_some());
+        }
+    }
+
+    #[test]
+    fn duration_out_of_range() {
+        for duration_ms in [0, MAX_DURATION_MS + 1] {
+            let err = rejected(&format!(
+                r#"{{"type":"state","state":"alert","intensity":1,"duration_ms":{duration_ms}}}"#
+            ));
+            assert_eq!(err.code, ErrorCode::InvalidMessage);
+            assert!(err.message.contains("`duration_ms`"), "{}", err.message);
+            assert_eq!(err.request_type.as_deref(), Some("state"));
+        }
+    }
+
+    #[test]
+    fn capture_paths_outside_the_capture_dir() {
+        for path in ["/tmp/x.png", "../x.png", "shots/x.png", "..", ".", ""] {
+            let err = rejected(&format!(r#"{{"type":"capture","path":"{path}"}}"#));
+            assert_eq!(err.code, ErrorCode::InvalidMessage, "{path}");
+            assert!(err.message.contains("`path`"), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let err = rejected(&format!(
+            r#"{{"type":"hello","version":{}}}"#,
+            MIN_PROTOCOL_VERSION - 1
+        ));
+        assert_eq!(err.code, ErrorCode::UnsupportedVersion);
+        assert_eq!(err.request_type.as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn valid_messages() {
+        assert!(matches!(
+            parse_message(r#"{"type":"hello","version":1,"priority":4,"client":"x"}"#),
+            Ok(IpcMessage::Hello {
+                version: 1,
+                priority: 4
+            })
+        ));
+        assert!(matches!(
+            parse_message(r#"{"type":"state","state":"alert","intensity":0.5,"timestamp":1}"#),
+            Ok(IpcMessage::State {
+                state: EntityState::Alert,
+                duration_ms: None,
+                priority: None,
+                persist: false,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_message(&format!(
+                r#"{{"type":"state","state":"idle","intensity":1,"duration_ms":{MAX_DURATION_MS},"priority":-2,"persist":true}}"#
+            )),
+            Ok(IpcMessage::State {
+                duration_ms: Some(MAX_DURATION_MS),
+                priority: Some(-2),
+                persist: true,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_message(r#"{"type":"pulse"}"#),
+            Ok(IpcMessage::Pulse { strength }) if strength == 1.0
+        ));
+        assert!(matches!(
+            parse_message(r#"{"type":"capture","path":"x.png"}"#),
+            Ok(IpcMessage::Capture { path: Some(_) })
+        ));
+        assert!(matches!(
+            parse_message(r#"{"type":"capture"}"#),
+            Ok(IpcMessage::Capture { path: None })
+        ));
+        for message_type in ["release", "pause", "resume"] {
+            let line = format!(r#"{{"type":"{message_type}"}}"#);
+            assert!(parse_message(&line).is_ok(), "{line}");
+        }
+    }
+
+    #[test]
+    fn drain_keeps_partial_lines() {
+        let mut buffer = b"{\"type\":\"pause\"}\n\n  \nnot json\n{\"type\":\"res".to_vec();
+        let messages = drain_messages(&mut buffer);
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], Ok(IpcMessage::Pause)));
+        assert!(messages[1].is_err());
+        assert_eq!(buffer, b"{\"type\":\"res");
+    }
 }
@@ -1,7 +1,22 @@
+mod capture;
+mod cli;
+mod clock;
+mod config;
+mod control;
+mod ctl;
+mod entity;
+#[cfg(test)]
+mod golden;
 mod gpu;
+mod headless;
 mod ipc;
+mod output;
+mod power;
+mod watch;
 
 use std::{
+    cmp::Reverse,
+    collections::HashMap,
     ffi::c_void,
     io::Read,
     path::PathBuf,
@@ -14,8 +29,13 @@ use calloop::{
     PostAction, RegistrationToken,
 };
 use calloop_wayland_source::WaylandSource;
-use gpu::{GpuRenderer, Uniforms};
+use capture::FrameCapture;
+use clock::{Clock, FixedStep, SIM_STEP};
+use config::{PowerConfig, PowerMode, SwarmMode};
+use entity::Entity;
+use gpu::{GpuContext, ShaderStage, Swarm, Uniforms, FULL_VIEWPORT};
 use log::{debug, error, info, warn};
+use output::{OutputSurface, OwnSwarm};
 use smithay_client_toolkit::{
     compositor::{CompositorHandler, CompositorState},
     delegate_compositor, delegate_layer, delegate_output, delegate_registry,
@@ -31,490 +51,205 @@ use smithay_client_toolkit::{
     },
 };
 use wayland_client::{
+    delegate_noop,
     globals::registry_queue_init,
     protocol::{wl_output, wl_surface},
-    Connection, Proxy, QueueHandle,
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::{
+    fractional_scale::v1::client::{
+        wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+    },
+    viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+};
+use wayland_protocols_wlr::output_power_management::v1::client::{
+    zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+    zwlr_output_power_v1::{self, ZwlrOutputPowerV1},
 };
 
-#[derive(Debug, Copy, Clone)]
-struct SmoothValue {
-    current: f32,
-    from: f32,
-    target: f32,
-    started_at: Instant,
-}
-
-impl SmoothValue {
-    fn new(value: f32, now: Instant) -> Self {
-        Self {
-            current: value,
-            from: value,
-            target: value,
-            started_at: now,
-        }
-    }
-
-    fn set_target(&mut self, target: f32, now: Instant) {
-        if self.target.to_bits() == target.to_bits() {
-            return;
-        }
-        self.from = self.current;
-        self.target = target;
-        self.started_at = now;
-    }
-
-    fn update(&mut self, now: Instant, duration: Duration) {
-        if self.current.to_bits() == self.target.to_bits() {
-            return;
-        }
-
-        let duration_s = duration.as_secs_f32();
-        if duration_s <= 0.000_1 {
-            self.current = self.target;
-            return;
-        }
-
-        let elapsed_s = now.duration_since(self.started_at).as_secs_f32();
-        let mut t = (elapsed_s / duration_s).clamp(0.0, 1.0);
-        t = t * t * (3.0 - 2.0 * t);
-        self.current = self.from + (self.target - self.from) * t;
-        if t >= 1.0 {
-            self.current = self.target;
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-struct StateBlend {
-    current_state: u32,
-    target_state: u32,
-    blend: SmoothValue,
-}
-
-impl StateBlend {
-    fn new(state: u32, now: Instant) -> Self {
-        Self {
-            current_state: state.min(5),
-            target_state: state.min(5),
-            blend: SmoothValue::new(0.0, now),
-        }
-    }
-
-    fn set_target(&mut self, target_state: u32, now: Instant) {
-        let target_state = target_state.min(5);
-        if self.target_state == target_state {
-            return;
-        }
-
-        if self.current_state != self.target_state && self.blend.current >= 0.5 {
-            self.current_state = self.target_state;
-        }
-
-        self.target_state = target_state;
-        if self.current_state == self.target_state {
-            self.blend = SmoothValue::new(0.0, now);
-            return;
-        }
-
-        self.blend = SmoothValue::new(0.0, now);
-        self.blend.set_target(1.0, now);
-    }
-
-    fn update(&mut self, now: Instant, duration: Duration) {
-        if self.current_state == self.target_state {
-            self.blend = SmoothValue::new(0.0, now);
-            return;
-        }
-
-        self.blend.update(now, duration);
-        if self.blend.current >= 1.0 {
-            self.current_state = self.target_state;
-            self.blend = SmoothValue::new(0.0, now);
-        }
-    }
-
-    fn blend_factor(&self) -> f32 {
-        self.blend.current
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-struct MotionParams {
-    base_scale: f32,
-    scale_pulse: f32,
-    pulse_speed: f32,
-    drift_amp: [f32; 2],
-    drift_speed: f32,
-    bounce_mix: f32,
-    bounce_speed: f32,
-    base_offset: [f32; 2],
-    smooth_time: f32,
-}
-
-impl MotionParams {
-    fn for_state(state: u32, intensity: f32) -> Self {
-        let intensity = intensity.clamp(0.0, 1.0);
-        let energy = 0.35 + 0.65 * intensity;
-
-        let mut params = match state {
-            1 => Self {
-                base_scale: 1.25,
-                scale_pulse: 0.1,
-                pulse_speed: 1.1,
-                drift_amp: [0.16, 0.12],
-                drift_speed: 0.45,
-                bounce_mix: 0.6,
-                bounce_speed: 0.25,
-                base_offset: [0.0, 0.05],
-                smooth_time: 0.7,
-            },
-            2 => Self {
-                base_scale: 0.7,
-                scale_pulse: 0.02,
-                pulse_speed: 0.5,
-                drift_amp: [0.02, 0.015],
-                drift_speed: 0.12,
-                bounce_mix: 0.0,
-                bounce_speed: 0.1,
-                base_offset: [0.0, 0.0],
-                smooth_time: 0.8,
-            },
-            3 => Self {
-                base_scale: 1.05,
-                scale_pulse: 0.16,
-                pulse_speed: 1.6,
-                drift_amp: [0.12, 0.1],
-                drift_speed: 0.8,
-                bounce_mix: 0.4,
-                bounce_speed: 0.9,
-                base_offset: [0.02, 0.0],
-                smooth_time: 0.45,
-            },
-            4 => Self {
-                base_scale: 1.45,
-                scale_pulse: 0.22,
-                pulse_speed: 2.2,
-                drift_amp: [0.2, 0.18],
-                drift_speed: 1.2,
-                bounce_mix: 0.8,
-                bounce_speed: 1.1,
-                base_offset: [0.0, 0.1],
-                smooth_time: 0.35,
-            },
-            5 => Self {
-                base_scale: 0.6,
-                scale_pulse: 0.02,
-                pulse_speed: 0.35,
-                drift_amp: [0.03, 0.025],
-                drift_speed: 0.08,
-                bounce_mix: 0.0,
-                bounce_speed: 0.1,
-                base_offset: [0.0, -0.22],
-                smooth_time: 1.4,
-            },
-            _ => Self {
-                base_scale: 1.0,
-                scale_pulse: 0.04,
-                pulse_speed: 0.6,
-                drift_amp: [0.06, 0.04],
-                drift_speed: 0.2,
-                bounce_mix: 0.0,
-                bounce_speed: 0.15,
-                base_offset: [0.0, 0.0],
-                smooth_time: 1.1,
-            },
-        };
-
-        params.drift_amp[0] *= energy;
-        params.drift_amp[1] *= energy;
-        params.scale_pulse *= 0.3 + 0.7 * intensity;
-        params.drift_speed *= 0.4 + 0.6 * intensity;
-        params.bounce_speed *= 0.4 + 0.6 * intensity;
-        params.bounce_mix *= 0.2 + 0.8 * intensity;
-        params.pulse_speed *= 0.5 + 0.5 * intensity;
-
-        params
-    }
-
-    fn lerp(self, other: Self, t: f32) -> Self {
-        Self {
-            base_scale: lerp(self.base_scale, other.base_scale, t),
-            scale_pulse: lerp(self.scale_pulse, other.scale_pulse, t),
-            pulse_speed: lerp(self.pulse_speed, other.pulse_speed, t),
-            drift_amp: lerp2(self.drift_amp, other.drift_amp, t),
-            drift_speed: lerp(self.drift_speed, other.drift_speed, t),
-            bounce_mix: lerp(self.bounce_mix, other.bounce_mix, t),
-            bounce_speed: lerp(self.bounce_speed, other.bounce_speed, t),
-            base_offset: lerp2(self.base_offset, other.base_offset, t),
-            smooth_time: lerp(self.smooth_time, other.smooth_time, t),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-struct SynapticParams {
-    damping: f32,
-    noise_strength: f32,
-    attraction: f32,
-    speed: f32,
-    trail_fade: f32,
-    glow_intensity: f32,
-    color_shift: f32,
-}
-
-impl SynapticParams {
-    fn for_state(state: u32, intensity: f32) -> Self {
-        let intensity = intensity.clamp(0.0, 1.0);
-        let mut params = match state {
-            1 => Self {
-                damping: 0.995,
-                noise_strength: 9.0,
-                attraction: 0.45,
-                speed: 1.5,
-                trail_fade: 0.992,
-                glow_intensity: 1.05,
-                color_shift: 0.15,
-            },
-            2 => Self {
-                damping: 0.9992,
-                noise_strength: 1.2,
-                attraction: 2.0,
-                speed: 0.45,
-                trail_fade: 0.9985,
-                glow_intensity: 0.7,
-                color_shift: -0.08,
-            },
-            3 => Self {
-                damping: 0.993,
-                noise_strength: 14.0,
-                attraction: 0.35,
-                speed: 1.9,
-                trail_fade: 0.989,
-                glow_intensity: 1.2,
-                color_shift: 0.22,
-            },
-            4 => Self {
-                damping: 0.991,
-                noise_strength: 18.0,
-                attraction: 0.1,
-                speed: 2.6,
-                trail_fade: 0.984,
-                glow_intensity: 1.35,
-                color_shift: 0.3,
-            },
-            5 => Self {
-                damping: 0.9999,
-                noise_strength: 0.8,
-                attraction: 0.2,
-                speed: 0.3,
-                trail_fade: 0.9992,
-                glow_intensity: 0.6,
-                color_shift: -0.15,
-            },
-            _ => Self {
-                damping: 0.9975,
-                noise_strength: 4.5,
-                attraction: 0.35,
-                speed: 0.9,
-                trail_fade: 0.996,
-                glow_intensity: 0.85,
-                color_shift: 0.0,
-            },
-        };
-
-        params.glow_intensity *= 0.45 + 0.55 * intensity;
-        params.color_shift *= 0.35 + 0.65 * intensity;
-
-        params
-    }
-
-    fn lerp(self, other: Self, t: f32) -> Self {
-        Self {
-            damping: lerp(self.damping, other.damping, t),
-            noise_strength: lerp(self.noise_strength, other.noise_strength, t),
-            attraction: lerp(self.attraction, other.attraction, t),
-            speed: lerp(self.speed, other.speed, t),
-            trail_fade: lerp(self.trail_fade, other.trail_fade, t),
-            glow_intensity: lerp(self.glow_intensity, other.glow_intensity, t),
-            color_shift: lerp(self.color_shift, other.color_shift, t),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-struct MotionState {
-    pos_x: SmoothValue,
-    pos_y: SmoothValue,
-    scale: SmoothValue,
-}
-
-impl MotionState {
-    fn new(now: Instant) -> Self {
-        Self {
-            pos_x: SmoothValue::new(0.5, now),
-            pos_y: SmoothValue::new(0.5, now),
-            scale: SmoothValue::new(1.0, now),
+/// Runs the simulation steps of `swarm` that are due by `now`, returning the uniforms of the
+/// last one, if any.
+fn step_swarm(
+    gpu: &GpuContext,
+    clock: &Clock,
+    entity: &mut Entity,
+    swarm: &mut Swarm,
+    steps: &mut FixedStep,
+    now: Instant,
+    [width, height]: [u32; 2],
+) -> Option<Uniforms> {
+    let mut last = None;
+    for step in steps.due(now) {
+        if let Some(stats) = swarm.stats() {
+            entity.set_swarm_stats(stats);
         }
+        let uniforms = entity
+            .frame_uniforms(step, clock.secs_at(step), width, height, swarm.step_count())
+            .with_delta_time(SIM_STEP.as_secs_f32());
+        gpu.simulate(swarm, &uniforms);
+        last = Some(uniforms);
     }
-
-    fn update(&mut self, now: Instant, params: MotionParams, t: f32) -> ([f32; 2], f32) {
-        let smooth_time = params.smooth_time.max(0.05);
-        let smooth = Duration::from_secs_f32(smooth_time);
-        self.pos_x.update(now, smooth);
-        self.pos_y.update(now, smooth);
-        self.scale.update(now, smooth);
-
-        let target_pos = target_position(params, t);
-        let target_scale = target_scale(params, t);
-
-        self.pos_x.set_target(target_pos[0], now);
-        self.pos_y.set_target(target_pos[1], now);
-        self.scale.set_target(target_scale, now);
-
-        ([self.pos_x.current, self.pos_y.current], self.scale.current)
-    }
+    last
 }
 
-fn lerp(a: f32, b: f32, t: f32) -> f32 {
-    a + (b - a) * t
-}
-
-fn lerp2(a: [f32; 2], b: [f32; 2], t: f32) -> [f32; 2] {
-    [lerp(a[0], b[0], t), lerp(a[1], b[1], t)]
-}
-
-fn tri_wave(t: f32) -> f32 {
-    let f = t.fract();
-    if f < 0.5 {
-        f * 2.0
-    } else {
-        (1.0 - f) * 2.0
-    }
-}
-
-fn target_position(params: MotionParams, t: f32) -> [f32; 2] {
-    let base = [
-        (0.5 + params.base_offset[0]).clamp(0.05, 0.95),
-        (0.5 + params.base_offset[1]).clamp(0.05, 0.95),
-    ];
-    let drift = [
-        (t * params.drift_speed).sin() * params.drift_amp[0],
-        (t * params.drift_speed * 0.83 + 1.7).cos() * params.drift_amp[1],
-    ];
-    let bounce = [
-        lerp(0.08, 0.92, tri_wave(t * params.bounce_speed + 0.13)),
-        lerp(0.08, 0.92, tri_wave(t * params.bounce_speed * 0.93 + 0.57)),
-    ];
-
-    let mut pos = [base[0] + drift[0], base[1] + drift[1]];
-    pos[0] = lerp(pos[0], bounce[0], params.bounce_mix);
-    pos[1] = lerp(pos[1], bounce[1], params.bounce_mix);
-    pos[0] = pos[0].clamp(0.05, 0.95);
-    pos[1] = pos[1].clamp(0.05, 0.95);
-    pos
-}
-
-fn target_scale(params: MotionParams, t: f32) -> f32 {
-    let pulse = (t * params.pulse_speed).sin();
-    let wobble = (t * (params.pulse_speed * 0.4 + 0.7)).sin();
-    (params.base_scale + params.scale_pulse * pulse + params.scale_pulse * 0.35 * wobble)
-        .clamp(0.35, 2.5)
-}
-
-fn attach_ipc_client<'l>(
-    handle: &LoopHandle<'l, AppState>,
+/// Redraw interval for outputs without a frame callback outstanding.
+const FALLBACK_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+/// Fallback timer interval while suspended; frame callbacks and power events resume drawing
+/// without waiting for it.
+const SUSPENDED_TIMER_INTERVAL: Duration = Duration::from_millis(500);
+/// Longest time one frame's trails are faded for, so a long-hidden output does not come back
+/// black.
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+/// How long after its last frame an output stops pacing the shared swarm. Once no output is
+/// left pacing, the renderer suspends.
+const PACING_TIMEOUT: Duration = Duration::from_millis(250);
+/// Frame callbacks arriving this much before the frame rate cap allows still draw, to absorb
+/// jitter.
+const FRAME_CAP_SLACK: Duration = Duration::from_millis(2);
+/// How often `/sys/class/power_supply` is checked for a switch to or from battery.
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a `status` message goes out over IPC.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Registers a connection with the event loop and greets it with `hello`. `observer_path`
+/// is set for the connection to the observer, and unset for control clients.
+fn attach_peer(
+    handle: &LoopHandle<'static, AppState>,
     state: &mut AppState,
     stream: std::os::unix::net::UnixStream,
-    path: PathBuf,
+    observer_path: Option<PathBuf>,
 ) {
+    let id = state.next_peer_id;
+    let name = match &observer_path {
+        Some(path) => path.display().to_string(),
+        None => format!("control client {id}"),
+    };
+    let writer = match ipc::IpcWriter::new(&stream) {
+        Ok(writer) => writer,
+        Err(err) => {
+            warn!("Failed to set up IPC replies to {name}: {err}");
+            return;
+        }
+    };
     let Ok(token) = handle.insert_source(
         Generic::new(stream, Interest::READ, Mode::Level),
         move |readiness, stream, state| {
+            let Some(peer) = state.peers.get_mut(&id) else {
+                return Ok(PostAction::Remove);
+            };
             if readiness.error {
-                warn!("IPC socket reported error; disconnecting");
-                state.ipc_token = None;
-                state.ipc_path = None;
-                state.ipc_buffer.clear();
+                warn!("IPC socket of {} reported error; disconnecting", peer.name);
+                state.remove_peer(id);
                 return Ok(PostAction::Remove);
             }
 
-            let mut buffer = std::mem::take(&mut state.ipc_buffer);
             let mut disconnected = false;
             let mut tmp = [0u8; 4096];
-
             loop {
                 match (&**stream).read(&mut tmp) {
                     Ok(0) => {
                         disconnected = true;
                         break;
                     }
-                    Ok(n) => buffer.extend_from_slice(&tmp[..n]),
+                    Ok(n) => peer.buffer.extend_from_slice(&tmp[..n]),
                     Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
                     Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
                     Err(err) => {
-                        warn!("IPC read error: {err}");
+                        warn!("IPC read error from {}: {err}", peer.name);
                         disconnected = true;
                         break;
                     }
                 }
             }
 
-            let messages = ipc::drain_messages(&mut buffer);
-            state.ipc_buffer = buffer;
-
-            let now = Instant::now();
-            let mut changed = false;
-            for msg in messages {
-                match msg {
-                    ipc::IpcMessage::State {
-                        state: entity_state,
-                        intensity,
-                    } => {
-                        let new_state = entity_state.as_u32();
-                        let new_intensity = intensity.clamp(0.0, 1.0);
-                        if state.entity_state.target_state != new_state {
-                            state.entity_state.set_target(new_state, now);
-                            changed = true;
-                        }
-                        if state.intensity.target.to_bits() != new_intensity.to_bits() {
-                            state.intensity.set_target(new_intensity, now);
-                            changed = true;
-                        }
-                    }
+            for msg in ipc::drain_messages(&mut peer.buffer) {
+                if !state.handle_ipc_message(id, msg) {
+                    disconnected = true;
+                    break;
                 }
             }
 
-            if changed && state.configured {
-                state.draw();
-            }
-
             if disconnected {
-                if let Some(path) = state.ipc_path.as_ref() {
-                    warn!("IPC disconnected from {}", path.display());
-                } else {
-                    warn!("IPC disconnected");
+                if let Some(peer) = state.peers.get(&id) {
+                    info!("IPC disconnected: {}", peer.name);
                 }
-                state.ipc_token = None;
-                state.ipc_path = None;
-                state.ipc_buffer.clear();
+                state.remove_peer(id);
                 return Ok(PostAction::Remove);
             }
 
             Ok(PostAction::Continue)
         },
     ) else {
-        warn!("Failed to register IPC socket source");
+        warn!("Failed to register IPC socket source for {name}");
         return;
     };
 
-    state.ipc_token = Some(token);
-    state.ipc_path = Some(path.clone());
-    state.ipc_buffer.clear();
-    info!("IPC connected: {}", path.display());
+    info!("IPC connected: {name}");
+    state.next_peer_id += 1;
+    if observer_path.is_some() {
+        state.observer = Some((id, token));
+    }
+    state.peers.insert(id, ipc::Peer::new(name, writer));
+    let hello = ipc::RendererMessage::Hello {
+        version: ipc::PROTOCOL_VERSION,
+        messages: ipc::ACCEPTED_MESSAGES,
+        outputs: state.output_statuses(),
+    };
+    state.send_to(id, &hello);
 }
 
 fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "ctl") {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+        std::process::exit(ctl::main(args.skip(1)));
+    }
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let options = match cli::Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("sentinel-renderer: {err}");
+            eprintln!("{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if options.print_default_config {
+        print!("{}", config::Config::default_toml());
+        return;
+    }
+
+    let mut config = match config::Config::load(options.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{err:#}");
+            std::process::exit(1);
+        }
+    };
+
+    options.outputs.apply(&mut config.outputs);
+
+    let shader_dir = options.shader_dir.clone().or_else(|| {
+        std::env::var_os("SENTINEL_SHADER_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    });
+
+    if let Some(headless) = options.headless {
+        info!("Sentinel Renderer starting (headless)");
+        if let Err(err) = headless::run(&headless, &config, options.capture, shader_dir.as_deref())
+        {
+            error!("Headless run failed: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     info!("Sentinel Renderer starting");
 
     let conn = Connection::connect_to_env().expect("Failed to connect to Wayland");
@@ -523,95 +258,132 @@ fn main() {
 
     let compositor = CompositorState::bind(&globals, &qh).expect("wl_compositor not available");
     let layer_shell = LayerShell::bind(&globals, &qh).expect("layer_shell not available");
-    let surface = compositor.create_surface(&qh);
-
-    let layer_surface =
-        layer_shell.create_layer_surface(&qh, surface, Layer::Background, Some("sentinel"), None);
-
-    layer_surface.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
-    layer_surface.set_exclusive_zone(-1);
-    layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
-    layer_surface.commit();
+    // Without these, surfaces fall back to integer buffer scales and ignore `render_scale`.
+    let viewporter = globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ()).ok();
+    let fractional_scale_manager = viewporter.as_ref().and_then(|_| {
+        globals
+            .bind::<WpFractionalScaleManagerV1, _, _>(&qh, 1..=1, ())
+            .ok()
+    });
+    // Only used to notice outputs being powered off; without it they count as always on.
+    let output_power_manager = globals
+        .bind::<ZwlrOutputPowerManagerV1, _, _>(&qh, 1..=1, ())
+        .ok();
+    if viewporter.is_none() {
+        warn!("wp_viewporter not available; using integer scales and ignoring render_scale");
+    } else if fractional_scale_manager.is_none() {
+        info!("wp_fractional_scale_v1 not available; using integer scales");
+    }
 
     let display_ptr = NonNull::new(conn.display().id().as_ptr().cast::<c_void>())
         .expect("Wayland display pointer was null");
-    let surface_ptr = NonNull::new(layer_surface.wl_surface().id().as_ptr().cast::<c_void>())
-        .expect("Wayland surface pointer was null");
-
-    let entity_state = std::env::var("SENTINEL_ENTITY_STATE")
-        .ok()
-        .and_then(|v| v.parse::<u32>().ok())
-        .unwrap_or(0)
-        .min(5);
-    let intensity = std::env::var("SENTINEL_ENTITY_INTENSITY")
-        .ok()
-        .and_then(|v| v.parse::<f32>().ok())
-        .unwrap_or(1.0)
-        .clamp(0.0, 1.0);
-    let cycle_states = std::env::var("SENTINEL_ENTITY_CYCLE")
-        .ok()
-        .is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
-
-    let transition_duration = std::env::var("SENTINEL_TRANSITION_DURATION")
-        .ok()
-        .and_then(|v| v.parse::<f32>().ok())
-        .filter(|v| v.is_finite() && *v > 0.0)
-        .map(Duration::from_secs_f32)
-        .unwrap_or(Duration::from_millis(750));
-
-    let ipc_candidates = ipc::socket_candidates();
-
-    let gpu = GpuRenderer::new(display_ptr, surface_ptr, 256, 256)
+
+    let frame_capture = FrameCapture::new(options.capture).expect("Failed to set up frame capture");
+
+    let mut gpu = GpuContext::new_wayland(display_ptr, config.particles.backend)
         .expect("Failed to initialize wgpu renderer");
+    if let Some(dir) = &shader_dir {
+        gpu.load_shader_dir(dir);
+    }
+    if let Some(path) = &config.present_shader {
+        gpu.set_present_shader(Some(path));
+    }
+    let swarm_mode = config.outputs.swarm;
+    let particle_count = config.particles.count;
+    let shared_swarm = (swarm_mode == SwarmMode::Shared).then(|| gpu.create_swarm(particle_count));
 
-    let start_time = Instant::now();
+    let mut event_loop: EventLoop<'static, AppState> =
+        EventLoop::try_new().expect("Failed to create event loop");
+    let handle = event_loop.handle();
+
+    // Layer surfaces are created per output as `OutputHandler::new_output` reports them,
+    // starting with the outputs that already exist.
+    let clock = Clock::new();
+    let entity = Entity::from_config(&config, clock.now());
+    // The state to return to once every client's claim has ended.
+    let (_, initial_state, _) = entity.state();
+    let claims = control::StateClaims::new(
+        ipc::EntityState::from_u32(initial_state),
+        entity.intensity(),
+    );
     let mut state = AppState {
         registry_state: RegistryState::new(&globals),
         output_state: OutputState::new(&globals, &qh),
-        gpu: Some(gpu),
-        layer_surface: Some(layer_surface),
-        width: 256,
-        height: 256,
-        configured: false,
-        start_time,
-        loop_signal: None,
-        transition_duration,
-        entity_state: StateBlend::new(entity_state, start_time),
-        intensity: SmoothValue::new(intensity, start_time),
-        motion: MotionState::new(start_time),
-        cycle_states,
+        compositor,
+        layer_shell,
+        viewporter,
+        output_power_manager,
+        fractional_scale_manager,
+        qh: qh.clone(),
+        outputs: Vec::new(),
+        output_args: options.outputs.clone(),
+        outputs_config: config.outputs.clone(),
+        gpu,
+        swarm_mode,
+        shared_swarm,
+        particle_count,
+        shared_steps: FixedStep::default(),
+        shared_uniforms: None,
+        canvas_size: [1, 1],
+        entity,
+        clock,
+        loop_signal: Some(event_loop.get_signal()),
+        loop_handle: handle.clone(),
+        config_arg: options.config.clone(),
+        config_watcher: None,
+        present_shader: config.present_shader.clone(),
+        max_fps: config.max_fps,
+        power: config.power.clone(),
+        on_battery: power::on_battery(),
         frame_count: 0,
-        ipc_token: None,
-        ipc_buffer: Vec::new(),
-        ipc_path: None,
+        frame_capture,
+        ipc_candidates: ipc::socket_candidates(config.socket_path.as_deref()),
+        observer: None,
+        peers: HashMap::new(),
+        next_peer_id: 0,
+        claims,
+        control_path: control::socket_path(config.control_socket.as_deref()),
+        expiry_timer: None,
+        held: false,
+        last_status: (Instant::now(), 0),
     };
 
-    let mut event_loop: EventLoop<AppState> =
-        EventLoop::try_new().expect("Failed to create event loop");
-
-    state.loop_signal = Some(event_loop.get_signal());
-    let handle = event_loop.handle();
-
-    // Set up a timer for animation (60fps)
-    let timer = calloop::timer::Timer::from_duration(Duration::from_millis(16));
+    // Frame callbacks pace the animation. This timer only restarts outputs that have none
+    // outstanding, e.g. after the surface had no image to draw into.
+    let timer = calloop::timer::Timer::from_duration(FALLBACK_FRAME_INTERVAL);
     handle
         .insert_source(timer, |_, _, state| {
-            if state.configured {
-                state.draw();
+            for index in 0..state.outputs.len() {
+                state.redraw(index);
             }
-            TimeoutAction::ToDuration(Duration::from_millis(16))
+            state.update_suspended();
+            TimeoutAction::ToDuration(if state.clock.is_paused() {
+                SUSPENDED_TIMER_INTERVAL
+            } else {
+                FALLBACK_FRAME_INTERVAL
+            })
         })
         .expect("Failed to insert timer");
 
+    if state.low_power() {
+        info!("Low-power profile on");
+    }
+    let power_timer = calloop::timer::Timer::from_duration(POWER_POLL_INTERVAL);
+    handle
+        .insert_source(power_timer, |_, _, state| {
+            state.set_on_battery(power::on_battery());
+            TimeoutAction::ToDuration(POWER_POLL_INTERVAL)
+        })
+        .expect("Failed to insert power supply timer");
+
     // IPC reconnect loop (1Hz).
     let ipc_handle = handle.clone();
-    let ipc_candidates_clone = ipc_candidates.clone();
     let reconnect_timer = calloop::timer::Timer::from_duration(Duration::from_secs(1));
     handle
         .insert_source(reconnect_timer, move |_, _, state| {
-            if state.ipc_token.is_none() {
-                if let Some((stream, path)) = ipc::try_connect(&ipc_candidates_clone) {
-                    attach_ipc_client(&ipc_handle, state, stream, path);
+            if state.observer.is_none() {
+                if let Some((stream, path)) = ipc::try_connect(&state.ipc_candidates) {
+                    attach_peer(&ipc_handle, state, stream, Some(path));
                 } else {
                     debug!("IPC not available yet; will retry");
                 }
@@ -620,9 +392,53 @@ fn main() {
         })
         .expect("Failed to insert IPC reconnect timer");
 
+    let status_timer = calloop::timer::Timer::from_duration(STATUS_INTERVAL);
+    handle
+        .insert_source(status_timer, |_, _, state| {
+            state.send_status();
+            TimeoutAction::ToDuration(STATUS_INTERVAL)
+        })
+        .expect("Failed to insert IPC status timer");
+
     // Attempt an eager connect at startup (avoid waiting for first reconnect tick).
-    if let Some((stream, path)) = ipc::try_connect(&ipc_candidates) {
-        attach_ipc_client(&handle, &mut state, stream, path);
+    if let Some((stream, path)) = ipc::try_connect(&state.ipc_candidates) {
+        attach_peer(&handle, &mut state, stream, Some(path));
+    }
+
+    if let Some(path) = state.control_path.clone() {
+        let registered = control::ControlSocket::bind(&path).and_then(|socket| {
+            socket.register(&handle, |stream, state: &mut AppState| {
+                let handle = state.loop_handle.clone();
+                attach_peer(&handle, state, stream, None);
+            })
+        });
+        match registered {
+            Ok(_) => info!("Listening for control clients on {}", path.display()),
+            Err(err) => warn!("Control socket disabled: {err:#}"),
+        }
+    }
+
+    state.watch_config();
+
+    // Shader hot-reload (development): rebuild just the pipeline whose file changed.
+    if let Some(dir) = &shader_dir {
+        let paths: Vec<_> = state
+            .gpu
+            .backend()
+            .stages()
+            .iter()
+            .map(|stage| dir.join(stage.file_name()))
+            .collect();
+        let registered = watch::FileWatcher::new(paths.iter().map(PathBuf::as_path))
+            .map_err(anyhow::Error::from)
+            .and_then(|watcher| {
+                watcher.register(&handle, |changed, state: &mut AppState| {
+                    state.reload_shaders(&changed)
+                })
+            });
+        if let Err(err) = registered {
+            warn!("Shader hot-reload disabled: {err:#}");
+        }
     }
 
     // Insert the Wayland event source
@@ -641,86 +457,755 @@ fn main() {
 struct AppState {
     registry_state: RegistryState,
     output_state: OutputState,
-    // Drop order matters: `wgpu::Surface` inside `GpuRenderer` must be dropped before the
-    // underlying Wayland `wl_surface` owned by `LayerSurface`. Rust drops struct fields in
-    // declaration order, so keep `gpu` before `layer_surface`.
-    gpu: Option<GpuRenderer>,
-    layer_surface: Option<LayerSurface>,
-    width: u32,
-    height: u32,
-    configured: bool,
-    start_time: Instant,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+    viewporter: Option<WpViewporter>,
+    output_power_manager: Option<ZwlrOutputPowerManagerV1>,
+    fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    qh: QueueHandle<AppState>,
+    outputs: Vec<OutputSurface>,
+    /// `--output`/`--exclude-output`, reapplied over every reloaded config.
+    output_args: cli::OutputArgs,
+    /// Which outputs get a surface (see `OutputsConfig::selects`) and how they are scaled.
+    outputs_config: config::OutputsConfig,
+    gpu: GpuContext,
+    swarm_mode: SwarmMode,
+    /// The swarm all outputs show, in shared mode; driven by `entity` on the canvas
+    /// spanning every output.
+    shared_swarm: Option<Swarm>,
+    particle_count: u32,
+    shared_steps: FixedStep,
+    /// The uniforms of the shared swarm's last step, which outputs drawn in between reuse.
+    shared_uniforms: Option<Uniforms>,
+    canvas_size: [u32; 2],
+    /// Animation time, paused while no output is drawn.
+    clock: Clock,
     loop_signal: Option<LoopSignal>,
-    transition_duration: Duration,
-    entity_state: StateBlend,
-    intensity: SmoothValue,
-    motion: MotionState,
-    cycle_states: bool,
+    loop_handle: LoopHandle<'static, AppState>,
+    /// `--config` as given, so reloads resolve the file the same way startup did.
+    config_arg: Option<PathBuf>,
+    /// The watcher of the config file and the present shader's file.
+    config_watcher: Option<RegistrationToken>,
+    /// Custom present shader from the config, if any; takes precedence over `--shader-dir`.
+    present_shader: Option<PathBuf>,
+    /// `max_fps` from the config.
+    max_fps: f32,
+    power: PowerConfig,
+    on_battery: bool,
+    /// Drives the shared swarm. In per-output mode it only tracks the latest state so that
+    /// outputs plugged in later start from it.
+    entity: Entity,
     frame_count: u32,
-    ipc_token: Option<RegistrationToken>,
-    ipc_buffer: Vec<u8>,
-    ipc_path: Option<PathBuf>,
+    frame_capture: FrameCapture,
+    ipc_candidates: Vec<PathBuf>,
+    /// The connection to the observer, if there is one; the reconnect timer retries while
+    /// there isn't.
+    observer: Option<(ipc::PeerId, RegistrationToken)>,
+    /// Every open connection: the observer's and the control clients'.
+    peers: HashMap<ipc::PeerId, ipc::Peer>,
+    next_peer_id: ipc::PeerId,
+    /// The peers' competing `state` messages.
+    claims: control::StateClaims,
+    /// The timer for the earliest override to end, and when it fires.
+    expiry_timer: Option<(Instant, RegistrationToken)>,
+    /// The control socket's path, if it is on.
+    control_path: Option<PathBuf>,
+    /// Paused by a `pause` message: nothing is drawn until `resume`.
+    held: bool,
+    /// Wall time and `frame_count` at the last `status` message, for its frame rate.
+    last_status: (Instant, u32),
 }
 
 impl AppState {
-    fn draw(&mut self) {
-        if self.layer_surface.is_none() {
-            return;
+    /// Every entity that IPC messages and config changes apply to.
+    fn entities_mut(&mut self) -> impl Iterator<Item = &mut Entity> {
+        std::iter::once(&mut self.entity).chain(
+            self.outputs
+                .iter_mut()
+                .filter_map(|output| output.own_swarm.as_mut().map(|own| &mut own.entity)),
+        )
+    }
+
+    /// Forgets a connection once its source is gone from the event loop, handing the swarm
+    /// to the next claim if the peer's standing claim was shown.
+    fn remove_peer(&mut self, id: ipc::PeerId) {
+        self.peers.remove(&id);
+        if self.observer.is_some_and(|(observer, _)| observer == id) {
+            self.observer = None;
+        }
+        if let Some(claim) = self.claims.disconnect(id) {
+            self.apply_claim(claim);
         }
+    }
 
-        let Some(gpu) = self.gpu.as_mut() else {
+    /// Ends the state overrides that are due.
+    fn expire_claims(&mut self) {
+        if let Some(claim) = self.claims.expire(Instant::now()) {
+            self.apply_claim(claim);
+        }
+        self.schedule_expiry();
+    }
+
+    /// Points the expiry timer at the earliest override still running. Overrides run on wall
+    /// time, so `pause` does not stop them.
+    fn schedule_expiry(&mut self) {
+        let next = self.claims.next_expiry();
+        if self.expiry_timer.map(|(deadline, _)| deadline) == next {
+            return;
+        }
+        if let Some((_, token)) = self.expiry_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        let Some(next) = next else {
             return;
         };
+        let timer = calloop::timer::Timer::from_deadline(next);
+        match self.loop_handle.insert_source(timer, |_, _, state| {
+            state.expiry_timer = None;
+            state.expire_claims();
+            TimeoutAction::Drop
+        }) {
+            Ok(token) => self.expiry_timer = Some((next, token)),
+            Err(err) => warn!("Failed to insert state override timer: {err}"),
+        }
+    }
 
-        let now = Instant::now();
-        let t = self.start_time.elapsed().as_secs_f32();
+    fn send_to(&mut self, id: ipc::PeerId, message: &ipc::RendererMessage) {
+        if let Some(peer) = self.peers.get_mut(&id) {
+            peer.send(message);
+        }
+    }
 
-        if self.cycle_states {
-            let cycle_state = ((t / 8.0).floor() as u32) % 6;
-            self.entity_state.set_target(cycle_state, now);
+    fn broadcast(&mut self, message: &ipc::RendererMessage) {
+        for peer in self.peers.values_mut() {
+            peer.send(message);
         }
+    }
 
-        self.entity_state.update(now, self.transition_duration);
-        self.intensity.update(now, self.transition_duration);
+    fn apply_claim(&mut self, claim: control::Claim) {
+        // Changes show up in the next frame each output draws.
+        let now = self.clock.now();
+        for entity in self.entities_mut() {
+            entity.set_state(claim.state.as_u32(), now);
+            entity.set_intensity(claim.intensity, now);
+        }
+    }
 
-        let blend = self.entity_state.blend_factor();
-        let params_cur =
-            MotionParams::for_state(self.entity_state.current_state, self.intensity.current);
-        let params_tgt =
-            MotionParams::for_state(self.entity_state.target_state, self.intensity.current);
-        let motion_params = params_cur.lerp(params_tgt, blend);
-        let (position, scale) = self.motion.update(now, motion_params, t);
+    /// Acts on one message from peer `id`. Returns false if the connection should be closed.
+    fn handle_ipc_message(
+        &mut self,
+        id: ipc::PeerId,
+        message: Result<ipc::IpcMessage, ipc::IpcError>,
+    ) -> bool {
+        let Some(peer) = self.peers.get_mut(&id) else {
+            return false;
+        };
+        let message = match message {
+            Ok(message) => message,
+            Err(err) if err.code == ipc::ErrorCode::UnsupportedVersion => {
+                error!("{}: {}; disconnecting", peer.name, err.message);
+                peer.send(&err.into());
+                return false;
+            }
+            Err(err) => {
+                warn!("Rejected IPC message from {}: {}", peer.name, err.message);
+                peer.send(&err.into());
+                return true;
+            }
+        };
+        let now = self.clock.now();
+        match message {
+            ipc::IpcMessage::Hello { version, priority } => {
+                info!(
+                    "{} speaks protocol version {version}; using {}",
+                    peer.name,
+                    version.min(ipc::PROTOCOL_VERSION)
+                );
+                peer.priority = priority;
+            }
+            ipc::IpcMessage::State {
+                state: entity_state,
+                intensity,
+                duration_ms,
+                priority,
+                persist,
+            } => {
+                let priority = priority.unwrap_or(peer.priority);
+                let expires = duration_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+                if let Some(claim) =
+                    self.claims
+                        .claim(id, priority, entity_state, intensity, expires, persist)
+                {
+                    self.apply_claim(claim);
+                }
+                self.schedule_expiry();
+            }
+            ipc::IpcMessage::Release => {
+                if let Some(claim) = self.claims.release(id) {
+                    self.apply_claim(claim);
+                }
+                self.schedule_expiry();
+            }
+            ipc::IpcMessage::Gaze { x, y } => {
+                for entity in self.entities_mut() {
+                    entity.set_gaze(x, y, now);
+                }
+            }
+            ipc::IpcMessage::Pulse { strength } => {
+                for entity in self.entities_mut() {
+                    entity.pulse(strength, now);
+                }
+            }
+            ipc::IpcMessage::Pause => {
+                if !self.held {
+                    info!("Paused by {}", peer.name);
+                    self.held = true;
+                    self.clock.pause();
+                }
+            }
+            ipc::IpcMessage::Resume => {
+                if self.held {
+                    info!("Resumed by {}", peer.name);
+                    self.held = false;
+                    for index in 0..self.outputs.len() {
+                        self.redraw(index);
+                    }
+                }
+            }
+            ipc::IpcMessage::Capture { path } => {
+                self.frame_capture.request(path.as_deref());
+            }
+            ipc::IpcMessage::Error { code, message } => {
+                warn!("{} rejected a message ({code}): {message}", peer.name);
+            }
+        }
+        true
+    }
 
-        let syn_cur = SynapticParams::for_state(self.entity_state.current_state, self.intensity.current);
-        let syn_tgt = SynapticParams::for_state(self.entity_state.target_state, self.intensity.current);
-        let syn_params = syn_cur.lerp(syn_tgt, blend);
+    fn output_statuses(&self) -> Vec<ipc::OutputStatus> {
+        self.outputs
+            .iter()
+            .filter(|output| output.configured)
+            .map(|output| {
+                let (width, height) = output.resolution();
+                ipc::OutputStatus {
+                    name: output.name.clone(),
+                    width,
+                    height,
+                }
+            })
+            .collect()
+    }
 
-        let frame_count = self.frame_count;
-        let uniforms = Uniforms::new(
-            t,
-            self.entity_state.current_state,
-            self.entity_state.target_state,
+    /// Reports what is on screen: the shared swarm's entity, or in per-output mode the first
+    /// output's.
+    fn send_status(&mut self) {
+        let now = Instant::now();
+        let (since, frames) = self.last_status;
+        let fps = self.frame_count.wrapping_sub(frames) as f32
+            / now.duration_since(since).as_secs_f32().max(f32::EPSILON);
+        self.last_status = (now, self.frame_count);
+
+        let entity = self
+            .outputs
+            .iter()
+            .find_map(|output| output.own_swarm.as_ref().map(|own| &own.entity))
+            .unwrap_or(&self.entity);
+        let (current, target, blend) = entity.state();
+        let message = ipc::RendererMessage::Status {
+            state: ipc::EntityState::from_u32(current),
+            target_state: ipc::EntityState::from_u32(target),
             blend,
-            self.intensity.current,
-            scale,
-            position,
-            self.width,
-            self.height,
-            frame_count,
-            syn_params.damping,
-            syn_params.noise_strength,
-            syn_params.attraction,
-            syn_params.speed,
-            syn_params.trail_fade,
-            syn_params.glow_intensity,
-            syn_params.color_shift,
+            intensity: entity.intensity(),
+            fps,
+            // Nothing is drawn while held, so whether outputs are visible is not known then.
+            paused: self.clock.is_paused() && !self.held,
+            held: self.held,
+            outputs: self.output_statuses(),
+        };
+        self.broadcast(&message);
+    }
+
+    /// (Re)starts config hot-reload, which also covers the custom present shader's file, so
+    /// it has to start over whenever `present_shader` changes. Not being able to watch only
+    /// costs the live reload.
+    fn watch_config(&mut self) {
+        if let Some(token) = self.config_watcher.take() {
+            self.loop_handle.remove(token);
+        }
+        let Some((config_path, _)) = config::resolve_path(self.config_arg.as_deref()) else {
+            return;
+        };
+        let files = std::iter::once(config_path.as_path()).chain(self.present_shader.as_deref());
+        let registered = watch::FileWatcher::new(files)
+            .map_err(anyhow::Error::from)
+            .and_then(|watcher| {
+                watcher.register(&self.loop_handle, move |changed, state: &mut AppState| {
+                    if changed.contains(&config_path) {
+                        state.reload_config();
+                    } else if let Some(path) = state.present_shader.clone() {
+                        if changed.contains(&path) {
+                            state.gpu.set_present_shader(Some(&path));
+                        }
+                    }
+                })
+            });
+        match registered {
+            Ok(token) => self.config_watcher = Some(token),
+            Err(err) => warn!("Config hot-reload disabled: {err:#}"),
+        }
+    }
+
+    /// Re-reads the config file and applies it to the running renderer. An invalid file is
+    /// reported and ignored, keeping the previous settings.
+    fn reload_config(&mut self) {
+        let mut config = match config::Config::load(self.config_arg.as_deref()) {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Ignoring config change: {err:#}");
+                return;
+            }
+        };
+        for entity in self.entities_mut() {
+            entity.apply_config(&config);
+        }
+
+        if config.present_shader != self.present_shader {
+            self.present_shader = config.present_shader.clone();
+            self.gpu.set_present_shader(self.present_shader.as_deref());
+            // This runs inside the current watcher's callback, which can't remove itself.
+            self.loop_handle.insert_idle(|state| state.watch_config());
+        }
+        if config.outputs.swarm != self.swarm_mode {
+            warn!("`outputs.swarm` changes take effect after a restart");
+        }
+        if config.particles.backend != self.gpu.backend() {
+            warn!("`particles.backend` changes take effect after a restart");
+        }
+        if config.particles.count != self.particle_count {
+            info!(
+                "Particle count changed to {}; restarting the swarm",
+                config.particles.count
+            );
+            self.particle_count = config.particles.count;
+            if let Some(swarm) = &mut self.shared_swarm {
+                *swarm = self.gpu.create_swarm(self.particle_count);
+            }
+            for output in &mut self.outputs {
+                if let Some(own) = &mut output.own_swarm {
+                    own.swarm = self.gpu.create_swarm(self.particle_count);
+                }
+            }
+        }
+
+        let render_scale = self.render_scale();
+        self.max_fps = config.max_fps;
+        self.power = config.power;
+        self.output_args.apply(&mut config.outputs);
+        if config.outputs != self.outputs_config {
+            self.outputs_config = config.outputs;
+            self.sync_outputs();
+        }
+        if self.render_scale() != render_scale {
+            self.rescale_outputs();
+        }
+
+        let candidates = ipc::socket_candidates(config.socket_path.as_deref());
+        if candidates != self.ipc_candidates {
+            info!("IPC socket changed; reconnecting");
+            self.ipc_candidates = candidates;
+            if let Some((id, token)) = self.observer.take() {
+                self.loop_handle.remove(token);
+                self.remove_peer(id);
+            }
+            if let Some((stream, path)) = ipc::try_connect(&self.ipc_candidates) {
+                let handle = self.loop_handle.clone();
+                attach_peer(&handle, self, stream, Some(path));
+            }
+        }
+        if control::socket_path(config.control_socket.as_deref()) != self.control_path {
+            warn!("`control_socket` changes take effect after a restart");
+        }
+    }
+
+    fn reload_shaders(&mut self, changed: &[PathBuf]) {
+        for path in changed {
+            let Some(&stage) = self
+                .gpu
+                .backend()
+                .stages()
+                .iter()
+                .find(|stage| path.file_name() == Some(stage.file_name().as_ref()))
+            else {
+                continue;
+            };
+            if stage == ShaderStage::Present && self.present_shader.is_some() {
+                continue;
+            }
+            match self.gpu.reload_shader_file(stage, path) {
+                Ok(()) => info!("Reloaded {}", path.display()),
+                Err(err) => error!("Keeping previous shader: {err:#}"),
+            }
+        }
+    }
+
+    fn is_selected(&self, output: &wl_output::WlOutput) -> bool {
+        output::is_selected(
+            &self.outputs_config,
+            self.output_state.info(output).as_ref(),
+        )
+    }
+
+    /// Adds surfaces for selected outputs that lack one and drops those of outputs that are no
+    /// longer selected.
+    fn sync_outputs(&mut self) {
+        let mut removed = false;
+        let selection = &self.outputs_config;
+        let output_state = &self.output_state;
+        self.outputs.retain(|output| {
+            let keep = output::is_selected(selection, output_state.info(&output.output).as_ref());
+            if !keep {
+                info!("Output {} deselected", output.name);
+                removed = true;
+            }
+            keep
+        });
+        if removed {
+            self.update_layout();
+        }
+
+        let missing: Vec<_> = self
+            .output_state
+            .outputs()
+            .filter(|output| !self.outputs.iter().any(|o| o.output == *output))
+            .collect();
+        for output in missing {
+            if self.is_selected(&output) {
+                self.add_output(output);
+            }
+        }
+    }
+
+    fn add_output(&mut self, output: wl_output::WlOutput) {
+        let info = self.output_state.info(&output);
+        let name = info
+            .as_ref()
+            .and_then(|info| info.name.clone())
+            .unwrap_or_else(|| format!("output-{}", output.id().protocol_id()));
+        let integer_scale = info
+            .as_ref()
+            .map_or(1, |info| info.scale_factor.max(1) as u32);
+        let transform = info.map_or(wl_output::Transform::Normal, |info| info.transform);
+
+        let qh = &self.qh;
+        let surface = self.compositor.create_surface(qh);
+        let layer_surface = self.layer_shell.create_layer_surface(
+            qh,
+            surface,
+            Layer::Background,
+            Some("sentinel"),
+            Some(&output),
         );
-        if let Err(e) = gpu.render(&uniforms) {
-            error!("wgpu render error: {e:?}");
-            if let Some(signal) = &self.loop_signal {
-                signal.stop();
+        layer_surface.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
+        layer_surface.set_exclusive_zone(-1);
+        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+        let wp_viewport = self
+            .viewporter
+            .as_ref()
+            .map(|viewporter| viewporter.get_viewport(layer_surface.wl_surface(), qh, ()));
+        let fractional_scale = self
+            .fractional_scale_manager
+            .as_ref()
+            .map(|manager| manager.get_fractional_scale(layer_surface.wl_surface(), qh, ()));
+        let power = self
+            .output_power_manager
+            .as_ref()
+            .map(|manager| manager.get_output_power(&output, qh, ()));
+        layer_surface.commit();
+
+        let surface_ptr = NonNull::new(layer_surface.wl_surface().id().as_ptr().cast::<c_void>())
+            .expect("Wayland surface pointer was null");
+        // SAFETY: the target is stored next to `layer_surface` in `OutputSurface`, which drops
+        // it first.
+        let target = match unsafe { self.gpu.create_surface_output(surface_ptr, 256, 256) } {
+            Ok(target) => target,
+            Err(err) => {
+                error!("Cannot render to output {name}: {err:#}");
+                return;
             }
+        };
+        let own_swarm = (self.swarm_mode == SwarmMode::PerOutput).then(|| OwnSwarm {
+            entity: self.entity,
+            swarm: self.gpu.create_swarm(self.particle_count),
+            steps: FixedStep::default(),
+            uniforms: None,
+        });
+
+        info!("Output {name} added");
+        self.outputs.push(OutputSurface {
+            output,
+            name,
+            target,
+            wp_viewport,
+            fractional_scale,
+            layer_surface,
+            width: 256,
+            height: 256,
+            transform,
+            scale: integer_scale * output::SCALE_DENOMINATOR,
+            configured: false,
+            frame_count: 0,
+            viewport: FULL_VIEWPORT,
+            own_swarm,
+            frame_pending: false,
+            last_frame: None,
+            power,
+            powered_on: true,
+        });
+        self.update_layout();
+    }
+
+    fn set_output_scale(&mut self, index: usize, scale: u32) {
+        let render_scale = self.render_scale();
+        let output = &mut self.outputs[index];
+        if output.scale == scale {
+            return;
+        }
+        output.scale = scale;
+        info!(
+            "Output {} scale: {}",
+            output.name,
+            scale as f32 / output::SCALE_DENOMINATOR as f32
+        );
+        if output.configured {
+            output.apply_size(&self.gpu, render_scale);
+            self.redraw(index);
+        }
+    }
+
+    fn set_output_transform(&mut self, index: usize, transform: wl_output::Transform) {
+        let render_scale = self.render_scale();
+        let output = &mut self.outputs[index];
+        if output.transform == transform {
+            return;
+        }
+        output.transform = transform;
+        info!("Output {} transform: {transform:?}", output.name);
+        if output.configured {
+            output.apply_size(&self.gpu, render_scale);
+            self.redraw(index);
+        }
+    }
+
+    fn update_layout(&mut self) {
+        let infos: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| self.output_state.info(&output.output))
+            .collect();
+        let (canvas_size, viewports) = output::layout_viewports(&infos);
+        self.canvas_size = canvas_size;
+        for (output, viewport) in self.outputs.iter_mut().zip(viewports) {
+            output.viewport = viewport;
+        }
+    }
+
+    fn low_power(&self) -> bool {
+        match self.power.mode {
+            PowerMode::Auto => self.on_battery,
+            PowerMode::Always => true,
+            PowerMode::Never => false,
+        }
+    }
+
+    fn render_scale(&self) -> f32 {
+        let render_scale = self.outputs_config.render_scale;
+        if self.low_power() {
+            render_scale.min(self.power.render_scale)
         } else {
+            render_scale
+        }
+    }
+
+    /// The shortest time between two frames of one output, if the frame rate is capped.
+    fn min_frame_interval(&self) -> Option<Duration> {
+        let max_fps = match (self.low_power(), self.max_fps) {
+            (true, 0.0) => self.power.max_fps,
+            (true, max_fps) => max_fps.min(self.power.max_fps),
+            (false, max_fps) => max_fps,
+        };
+        (max_fps > 0.0).then(|| Duration::from_secs_f32(1.0 / max_fps))
+    }
+
+    fn set_on_battery(&mut self, on_battery: bool) {
+        if on_battery == self.on_battery {
+            return;
+        }
+        let low_power = self.low_power();
+        self.on_battery = on_battery;
+        info!(
+            "Running on {}",
+            if on_battery { "battery" } else { "mains power" }
+        );
+        if self.low_power() != low_power {
+            info!("Low-power profile {}", if low_power { "off" } else { "on" });
+            self.rescale_outputs();
+        }
+    }
+
+    /// Applies the current render scale to every output. Only the render targets are
+    /// resized; the swarms carry on.
+    fn rescale_outputs(&mut self) {
+        let render_scale = self.render_scale();
+        for output in self.outputs.iter_mut().filter(|output| output.configured) {
+            output.apply_size(&self.gpu, render_scale);
+        }
+    }
+
+    /// Pauses the animation once no output has been drawn for a while: they are all hidden
+    /// behind other windows, so the compositor sends no frame callbacks, or powered off.
+    /// Drawing any output again resumes it.
+    fn update_suspended(&mut self) {
+        if self.clock.is_paused() || !self.outputs.iter().any(|output| output.configured) {
+            return;
+        }
+        if self.pacing_output(Instant::now()).is_none() {
+            info!("No output visible; pausing");
+            self.clock.pause();
+        }
+    }
+
+    /// The output whose frame callbacks step the shared swarm and the global frame counter:
+    /// the fastest of the outputs drawn recently. Hidden outputs get no frame callbacks, so
+    /// they drop out after `PACING_TIMEOUT`.
+    fn pacing_output(&self, now: Instant) -> Option<usize> {
+        let timeout = self
+            .min_frame_interval()
+            .map_or(PACING_TIMEOUT, |interval| PACING_TIMEOUT.max(interval * 2));
+        self.outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| {
+                output
+                    .last_frame
+                    .is_some_and(|last| now.duration_since(last) < timeout)
+            })
+            .max_by_key(|(index, output)| (self.refresh_rate(&output.output), Reverse(*index)))
+            .map(|(index, _)| index)
+    }
+
+    /// The output's current refresh rate in mHz, or 0 if unknown.
+    fn refresh_rate(&self, output: &wl_output::WlOutput) -> i32 {
+        self.output_state
+            .info(output)
+            .and_then(|info| {
+                info.modes
+                    .iter()
+                    .find(|mode| mode.current)
+                    .map(|mode| mode.refresh_rate)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Draws output `index` unless it is still waiting for a frame callback, which will draw
+    /// it soon enough.
+    fn redraw(&mut self, index: usize) {
+        if !self.outputs[index].frame_pending {
+            self.draw(index);
+        }
+    }
+
+    /// Renders output `index` and asks for a frame callback, so the next frame is drawn when
+    /// the compositor wants one: at the output's refresh rate, and not at all while the
+    /// background is hidden.
+    fn draw(&mut self, index: usize) {
+        if self.held || !self.outputs[index].configured || !self.outputs[index].powered_on {
+            return;
+        }
+        if self.clock.is_paused() {
+            info!(
+                "Output {} visible again; resuming",
+                self.outputs[index].name
+            );
+            self.clock.resume();
+        }
+
+        let wall = Instant::now();
+        let frame_time = self.outputs[index]
+            .last_frame
+            .map_or(SIM_STEP, |last| wall.duration_since(last))
+            .min(MAX_FRAME_TIME);
+        self.outputs[index].last_frame = Some(wall);
+        let pacing = self.pacing_output(wall) == Some(index);
+        let now = self.clock.now();
+        if pacing {
+            if let Some(swarm) = self.shared_swarm.as_mut() {
+                let uniforms = step_swarm(
+                    &self.gpu,
+                    &self.clock,
+                    &mut self.entity,
+                    swarm,
+                    &mut self.shared_steps,
+                    now,
+                    self.canvas_size,
+                );
+                self.shared_uniforms = uniforms.or(self.shared_uniforms);
+            }
+        }
+
+        let output = &mut self.outputs[index];
+        let (width, height) = output.resolution();
+        let transform = u32::from(output.transform);
+        let frame_count = output.frame_count;
+        let (swarm, uniforms, viewport) = match (&mut output.own_swarm, &self.shared_swarm) {
+            (Some(own), _) => {
+                let uniforms = step_swarm(
+                    &self.gpu,
+                    &self.clock,
+                    &mut own.entity,
+                    &mut own.swarm,
+                    &mut own.steps,
+                    now,
+                    [width, height],
+                );
+                own.uniforms = uniforms.or(own.uniforms);
+                (&own.swarm, own.uniforms, FULL_VIEWPORT)
+            }
+            (None, Some(swarm)) => (swarm, self.shared_uniforms, output.viewport),
+            (None, None) => return,
+        };
+        let Some(uniforms) = uniforms else {
+            return;
+        };
+        let uniforms = uniforms
+            .for_output(width, height, frame_count, viewport)
+            .with_transform(transform)
+            .with_delta_time(frame_time.as_secs_f32());
+
+        // The request is committed along with the frame by wgpu's present.
+        let surface = output.layer_surface.wl_surface();
+        surface.frame(&self.qh, surface.clone());
+        match self.gpu.render(&mut output.target, swarm, &uniforms) {
+            Ok(presented) => output.frame_pending = presented,
+            Err(e) => {
+                error!("wgpu render error: {e:?}");
+                if let Some(signal) = &self.loop_signal {
+                    signal.stop();
+                }
+                return;
+            }
+        }
+        output.frame_count = output.frame_count.wrapping_add(1);
+
+        if pacing {
+            let targets: Vec<_> = self
+                .outputs
+                .iter()
+                .filter(|output| output.configured)
+                .map(|output| (output.name.as_str(), &output.target))
+                .collect();
+            self.frame_capture
+                .after_frame(&self.gpu, &targets, self.frame_count);
             self.frame_count = self.frame_count.wrapping_add(1);
         }
     }
@@ -731,27 +1216,64 @@ impl CompositorHandler for AppState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_factor: i32,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
     ) {
+        // With `wp_fractional_scale_v1` the preferred scale arrives there instead.
+        if let Some(index) = self
+            .outputs
+            .iter()
+            .position(|o| o.fractional_scale.is_none() && o.layer_surface.wl_surface() == surface)
+        {
+            self.set_output_scale(index, new_factor.max(1) as u32 * output::SCALE_DENOMINATOR);
+        }
     }
 
     fn transform_changed(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_transform: wl_output::Transform,
+        surface: &wl_surface::WlSurface,
+        new_transform: wl_output::Transform,
     ) {
+        if let Some(index) = self
+            .outputs
+            .iter()
+            .position(|o| o.layer_surface.wl_surface() == surface)
+        {
+            self.set_output_transform(index, new_transform);
+        }
     }
 
     fn frame(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
+        surface: &wl_surface::WlSurface,
         _time: u32,
     ) {
+        if let Some(index) = self
+            .outputs
+            .iter()
+            .position(|o| o.layer_surface.wl_surface() == surface)
+        {
+            let min_frame_interval = self.min_frame_interval();
+            let output = &mut self.outputs[index];
+            output.frame_pending = false;
+            // Under a frame rate cap, skip callbacks until the next frame is due. Waiting for
+            // another callback rather than a timer keeps frames aligned with the display.
+            let now = Instant::now();
+            if let (Some(interval), Some(last)) = (min_frame_interval, output.last_frame) {
+                if now + FRAME_CAP_SLACK < last + interval {
+                    let surface = output.layer_surface.wl_surface();
+                    surface.frame(&self.qh, surface.clone());
+                    surface.commit();
+                    output.frame_pending = true;
+                    return;
+                }
+            }
+            self.draw(index);
+        }
     }
 
     fn surface_enter(
@@ -782,33 +1304,57 @@ impl OutputHandler for AppState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        if self.is_selected(&output) {
+            self.add_output(output);
+        } else if let Some(name) = self.output_state.info(&output).and_then(|info| info.name) {
+            info!("Output {name} not selected; leaving it alone");
+        }
     }
 
     fn update_output(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        // The description can change, and with it whether the output is selected.
+        self.sync_outputs();
+        self.update_layout();
+        let transform = self.output_state.info(&output).map(|info| info.transform);
+        if let (Some(index), Some(transform)) = (
+            self.outputs.iter().position(|o| o.output == output),
+            transform,
+        ) {
+            self.set_output_transform(index, transform);
+        }
     }
 
     fn output_destroyed(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        if let Some(index) = self.outputs.iter().position(|o| o.output == output) {
+            let removed = self.outputs.remove(index);
+            info!("Output {} removed", removed.name);
+            self.update_layout();
+        }
     }
 }
 
 impl LayerShellHandler for AppState {
-    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
-        self.gpu = None;
-        self.layer_surface = None;
-        if let Some(signal) = &self.loop_signal {
-            signal.stop();
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        if let Some(index) = self
+            .outputs
+            .iter()
+            .position(|o| o.layer_surface.wl_surface() == layer.wl_surface())
+        {
+            let removed = self.outputs.remove(index);
+            info!("Layer surface on {} closed by the compositor", removed.name);
+            self.update_layout();
         }
     }
 
@@ -816,25 +1362,37 @@ impl LayerShellHandler for AppState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _layer: &LayerSurface,
+        layer: &LayerSurface,
         configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
+        let Some(index) = self
+            .outputs
+            .iter()
+            .position(|o| o.layer_surface.wl_surface() == layer.wl_surface())
+        else {
+            return;
+        };
+        let render_scale = self.render_scale();
+        let output = &mut self.outputs[index];
         if configure.new_size.0 > 0 {
-            self.width = configure.new_size.0;
+            output.width = configure.new_size.0;
         }
         if configure.new_size.1 > 0 {
-            self.height = configure.new_size.1;
+            output.height = configure.new_size.1;
         }
 
-        info!("Display resolution: {}x{}", self.width, self.height);
-        self.configured = true;
-        if let Some(gpu) = self.gpu.as_mut() {
-            gpu.resize(self.width, self.height);
-        }
+        output.configured = true;
+        output.apply_size(&self.gpu, render_scale);
+        let (width, height) = output.target.size();
+        info!(
+            "Output {} resolution: {}x{} ({}x{} logical)",
+            output.name, width, height, output.width, output.height
+        );
+        self.update_layout();
 
-        // Draw initial frame
-        self.draw();
+        // Draw the initial frame, which starts the frame callbacks.
+        self.redraw(index);
     }
 }
 
@@ -849,3 +1407,75 @@ delegate_compositor!(AppState);
 delegate_output!(AppState);
 delegate_layer!(AppState);
 delegate_registry!(AppState);
+delegate_noop!(AppState: WpViewporter);
+delegate_noop!(AppState: WpViewport);
+delegate_noop!(AppState: WpFractionalScaleManagerV1);
+delegate_noop!(AppState: ZwlrOutputPowerManagerV1);
+
+impl Dispatch<ZwlrOutputPowerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrOutputPowerV1,
+        event: zwlr_output_power_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(index) = state
+            .outputs
+            .iter()
+            .position(|o| o.power.as_ref() == Some(proxy))
+        else {
+            return;
+        };
+        let output = &mut state.outputs[index];
+        match event {
+            zwlr_output_power_v1::Event::Mode {
+                mode: WEnum::Value(mode),
+            } => {
+                let powered_on = mode == zwlr_output_power_v1::Mode::On;
+                if powered_on != output.powered_on {
+                    info!(
+                        "Output {} powered {}",
+                        output.name,
+                        if powered_on { "on" } else { "off" }
+                    );
+                    output.powered_on = powered_on;
+                    if powered_on {
+                        state.redraw(index);
+                    }
+                }
+            }
+            zwlr_output_power_v1::Event::Failed => {
+                debug!("Power state of output {} unavailable", output.name);
+                if let Some(power) = output.power.take() {
+                    power.destroy();
+                }
+                output.powered_on = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        proxy: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let wp_fractional_scale_v1::Event::PreferredScale { scale } = event else {
+            return;
+        };
+        if let Some(index) = state
+            .outputs
+            .iter()
+            .position(|o| o.fractional_scale.as_ref() == Some(proxy))
+        {
+            state.set_output_scale(index, scale);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::Instant;
+
+use smithay_client_toolkit::{
+    output::OutputInfo,
+    shell::{wlr_layer::LayerSurface, WaylandSurface},
+};
+use wayland_client::protocol::wl_output;
+use wayland_protocols::wp::{
+    fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+    viewporter::client::wp_viewport::WpViewport,
+};
+use wayland_protocols_wlr::output_power_management::v1::client::zwlr_output_power_v1::ZwlrOutputPowerV1;
+
+use crate::{
+    clock::FixedStep,
+    config::OutputsConfig,
+    entity::Entity,
+    gpu::{GpuContext, OutputTarget, Swarm, Uniforms, FULL_VIEWPORT},
+};
+
+/// `wp_fractional_scale_v1` expresses scales in 120ths.
+pub const SCALE_DENOMINATOR: u32 = 120;
+
+/// The background layer surface on one `wl_output`, and what is drawn into it.
+pub struct OutputSurface {
+    pub output: wl_output::WlOutput,
+    pub name: String,
+    // Drop order matters: the `wgpu::Surface` inside `target` must be dropped before the
+    // `wl_surface` owned by `layer_surface`, and Rust drops fields in declaration order.
+    pub target: OutputTarget,
+    /// Present when the compositor supports `wp_viewporter`; maps the buffer onto the
+    /// logical size, whatever its pixel size.
+    pub wp_viewport: Option<WpViewport>,
+    pub fractional_scale: Option<WpFractionalScaleV1>,
+    pub layer_surface: LayerSurface,
+    /// Logical size, as configured by the compositor.
+    pub width: u32,
+    pub height: u32,
+    /// The output's transform, which buffers are drawn with so the compositor need not rotate
+    /// them.
+    pub transform: wl_output::Transform,
+    /// Preferred scale in 120ths: from `wp_fractional_scale_v1` if available, otherwise the
+    /// integer `wl_surface` scale.
+    pub scale: u32,
+    pub configured: bool,
+    pub frame_count: u32,
+    /// This output's part of the shared swarm's canvas.
+    pub viewport: [f32; 4],
+    /// The output's own swarm when swarms are per output.
+    pub own_swarm: Option<OwnSwarm>,
+    /// A frame callback was requested and has not fired yet.
+    pub frame_pending: bool,
+    pub last_frame: Option<Instant>,
+    /// Reports the output being powered off (DPMS), if the compositor supports it.
+    pub power: Option<ZwlrOutputPowerV1>,
+    pub powered_on: bool,
+}
+
+/// An output's independent swarm, with the entity driving it.
+pub struct OwnSwarm {
+    pub entity: Entity,
+    pub swarm: Swarm,
+    pub steps: FixedStep,
+    /// Uniforms of the latest simulation step.
+    pub uniforms: Option<Uniforms>,
+}
+
+impl OutputSurface {
+    /// Pixel size of the render targets: the logical size at the output's scale, reduced by
+    /// `render_scale` when a viewport can stretch the result back to the logical size, and
+    /// turned to the output's native orientation.
+    pub fn buffer_size(&self, render_scale: f32) -> (u32, u32) {
+        let mut scale = self.scale as f32 / SCALE_DENOMINATOR as f32;
+        if self.wp_viewport.is_some() {
+            scale *= render_scale;
+        }
+        let scaled = |logical: u32| ((logical as f32 * scale).round() as u32).max(1);
+        swap_if_rotated(self.transform, (scaled(self.width), scaled(self.height)))
+    }
+
+    /// Pixel size of the render targets in the surface's orientation, for
+    /// `Uniforms.resolution`.
+    pub fn resolution(&self) -> (u32, u32) {
+        swap_if_rotated(self.transform, self.target.size())
+    }
+
+    /// Resizes the render targets after a change of logical size, scale or `render_scale`, and
+    /// tells the compositor how the buffer maps onto the surface from the next commit on.
+    pub fn apply_size(&mut self, gpu: &GpuContext, render_scale: f32) {
+        match &self.wp_viewport {
+            Some(viewport) => viewport.set_destination(self.width as i32, self.height as i32),
+            None => self
+                .layer_surface
+                .wl_surface()
+                .set_buffer_scale((self.scale / SCALE_DENOMINATOR).max(1) as i32),
+        }
+        self.layer_surface
+            .wl_surface()
+            .set_buffer_transform(self.transform);
+        let (width, height) = self.buffer_size(render_scale);
+        gpu.resize(&mut self.target, width, height);
+    }
+}
+
+impl Drop for OutputSurface {
+    fn drop(&mut self) {
+        // These two are tied to the `wl_surface` and have to go before it.
+        if let Some(fractional_scale) = self.fractional_scale.take() {
+            fractional_scale.destroy();
+        }
+        if let Some(viewport) = self.wp_viewport.take() {
+            viewport.destroy();
+        }
+        if let Some(power) = self.power.take() {
+            power.destroy();
+        }
+    }
+}
+
+fn swap_if_rotated(transform: wl_output::Transform, (width, height): (u32, u32)) -> (u32, u32) {
+    use wl_output::Transform;
+    match transform {
+        Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => {
+            (height, width)
+        }
+        _ => (width, height),
+    }
+}
+
+/// Whether `selection` picks the output described by `info`.
+pub fn is_selected(selection: &OutputsConfig, info: Option<&OutputInfo>) -> bool {
+    selection.selects(
+        info.and_then(|info| info.name.as_deref()),
+        info.and_then(|info| info.description.as_deref()),
+    )
+}
+
+/// Lays outputs out on one canvas spanning the desktop, using their logical positions and
+/// sizes. Returns the canvas size and each output's viewport on it; outputs the compositor
+/// gave no geometry for see the whole canvas.
+pub fn layout_viewports(infos: &[Option<OutputInfo>]) -> ([u32; 2], Vec<[f32; 4]>) {
+    let rects: Vec<Option<[i32; 4]>> = infos
+        .iter()
+        .map(|info| {
+            let info = info.as_ref()?;
+            let (x, y) = info.logical_position?;
+            let (w, h) = info.logical_size?;
+            Some([x, y, w, h])
+        })
+        .collect();
+    layout_rects(&rects)
+}
+
+/// [`layout_viewports`] on the outputs' logical `[x, y, width, height]`.
+fn layout_rects(rects: &[Option<[i32; 4]>]) -> ([u32; 2], Vec<[f32; 4]>) {
+    let rects: Vec<Option<[i32; 4]>> = rects
+        .iter()
+        .map(|rect| rect.filter(|[_, _, w, h]| *w > 0 && *h > 0))
+        .collect();
+
+    let Some(bounds) = rects.iter().flatten().copied().reduce(|a, b| {
+        let x0 = a[0].min(b[0]);
+        let y0 = a[1].min(b[1]);
+        let x1 = (a[0] + a[2]).max(b[0] + b[2]);
+        let y1 = (a[1] + a[3]).max(b[1] + b[3]);
+        [x0, y0, x1 - x0, y1 - y0]
+    }) else {
+        return ([1, 1], vec![FULL_VIEWPORT; rects.len()]);
+    };
+
+    let [x0, y0, width, height] = bounds.map(|v| v as f32);
+    let viewports = rects
+        .iter()
+        .map(|rect| match rect {
+            Some([x, y, w, h]) => [
+                (*x as f32 - x0) / width,
+                (*y as f32 - y0) / height,
+                *w as f32 / width,
+                *h as f32 / height,
+            ],
+            None => FULL_VIEWPORT,
+        })
+        .collect();
+    ([bounds[2] as u32, bounds[3] as u32], viewports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_by_side() {
+        let (canvas, viewports) =
+            layout_rects(&[Some([0, 0, 1920, 1080]), Some([1920, 0, 1280, 720])]);
+        assert_eq!(canvas, [3200, 1080]);
+        assert_eq!(viewports[0], [0.0, 0.0, 0.6, 1.0]);
+        assert_eq!(viewports[1], [0.6, 0.0, 0.4, 720.0 / 1080.0]);
+    }
+
+    #[test]
+    fn offset_origins() {
+        // A monitor left of and above the primary one puts the canvas origin at its corner.
+        let (canvas, viewports) =
+            layout_rects(&[Some([0, 0, 1000, 1000]), Some([-1000, -500, 1000, 500])]);
+        assert_eq!(canvas, [2000, 1500]);
+        assert_eq!(viewports[0], [0.5, 500.0 / 1500.0, 0.5, 1000.0 / 1500.0]);
+        assert_eq!(viewports[1], [0.0, 0.0, 0.5, 500.0 / 1500.0]);
+    }
+
+    #[test]
+    fn missing_geometry_sees_the_whole_canvas() {
+        let (canvas, viewports) =
+            layout_rects(&[None, Some([100, 100, 800, 600]), Some([0, 0, 0, 600])]);
+        assert_eq!(canvas, [800, 600]);
+        assert_eq!(viewports, vec![FULL_VIEWPORT, FULL_VIEWPORT, FULL_VIEWPORT]);
+
+        let (canvas, viewports) = layout_rects(&[None, None]);
+        assert_eq!(canvas, [1, 1]);
+        assert_eq!(viewports, vec![FULL_VIEWPORT; 2]);
+        assert_eq!(layout_viewports(&[None]), ([1, 1], vec![FULL_VIEWPORT]));
+    }
+}
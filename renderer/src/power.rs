@@ -0,0 +1,98 @@
+use std::{fs, path::Path};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// Whether the machine is running on battery: some battery is discharging and no mains or
+/// USB supply is online. Machines without a battery, or whose supplies cannot be read,
+/// count as on mains power.
+pub fn on_battery() -> bool {
+    on_battery_in(Path::new(POWER_SUPPLY_DIR))
+}
+
+fn on_battery_in(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    let read = |path: &Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .map(|value| value.trim().to_owned())
+            .unwrap_or_default()
+    };
+
+    let mut discharging = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match read(&path, "type").as_str() {
+            "Mains" | "USB" if read(&path, "online") == "1" => return false,
+            "Battery" if read(&path, "status") == "Discharging" => discharging = true,
+            _ => {}
+        }
+    }
+    discharging
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fake `/sys/class/power_supply`, removed when dropped.
+    struct SupplyDir(PathBuf);
+
+    impl SupplyDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("sentinel-power-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn supply(self, name: &str, files: &[(&str, &str)]) -> Self {
+            let supply = self.0.join(name);
+            fs::create_dir(&supply).unwrap();
+            for (file, contents) in files {
+                fs::write(supply.join(file), format!("{contents}\n")).unwrap();
+            }
+            self
+        }
+    }
+
+    impl Drop for SupplyDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn mains_online() {
+        let dir = SupplyDir::new("mains-online")
+            .supply("AC", &[("type", "Mains"), ("online", "1")])
+            .supply("BAT0", &[("type", "Battery"), ("status", "Discharging")]);
+        assert!(!on_battery_in(&dir.0));
+    }
+
+    #[test]
+    fn mains_offline_and_discharging() {
+        let dir = SupplyDir::new("mains-offline")
+            .supply("AC", &[("type", "Mains"), ("online", "0")])
+            .supply("BAT0", &[("type", "Battery"), ("status", "Discharging")]);
+        assert!(on_battery_in(&dir.0));
+    }
+
+    #[test]
+    fn battery_not_discharging() {
+        let dir = SupplyDir::new("charging")
+            .supply("AC", &[("type", "Mains"), ("online", "0")])
+            .supply("BAT0", &[("type", "Battery"), ("status", "Charging")]);
+        assert!(!on_battery_in(&dir.0));
+    }
+
+    #[test]
+    fn no_supplies() {
+        let dir = SupplyDir::new("empty");
+        assert!(!on_battery_in(&dir.0));
+        assert!(!on_battery_in(&dir.0.join("missing")));
+    }
+}
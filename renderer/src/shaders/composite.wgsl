@@ -0,0 +1,67 @@
+// Trail pass for the compute backend: fades the previous trail and adds this frame's light
+// from sprites.wgsl, the way render.wgsl does with the glow it computes itself.
+
+struct Uniforms {
+  time: f32,
+  intensity: f32,
+  blend_factor: f32,
+  scale: f32,
+  current_state: u32,
+  target_state: u32,
+  frame_count: u32,
+  seed: u32,
+  resolution: vec2<f32>,
+  position: vec2<f32>,
+  damping: f32,
+  noise_strength: f32,
+  attraction: f32,
+  speed: f32,
+  trail_fade: f32,
+  glow_intensity: f32,
+  color_shift: f32,
+  transform: u32,
+  mods: vec4<f32>,
+  force_gain: vec2<f32>,
+  delta_time: f32,
+  trail_steps: u32,
+  palette_frequency: vec4<f32>,
+  palette_phase: vec4<f32>,
+  viewport: vec4<f32>,
+  particle_count: u32,
+  flock_radius: vec4<f32>,
+  flock_weight: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> u: Uniforms;
+@group(1) @binding(0) var light_tex: texture_2d<f32>;
+@group(2) @binding(0) var prev_render: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+  var positions = array<vec2<f32>, 3>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(3.0, -1.0),
+    vec2<f32>(-1.0, 3.0),
+  );
+  return vec4<f32>(positions[vertex_index], 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+  let coord = vec2<i32>(i32(frag_coord.x), i32(frag_coord.y));
+  var cola = textureLoad(light_tex, coord, 0).xyz;
+  cola = cola * u.glow_intensity * (0.55 + 0.45 * u.intensity);
+  let colb = textureLoad(prev_render, coord, 0).xyz;
+
+  // `trail_fade` is per 1/60 s; scale the fade and the new light by the time this frame
+  // covers so trails look the same at any frame rate.
+  let frames = u.delta_time * 60.0;
+  var col = (cola * frames + colb) * pow(u.trail_fade, frames);
+  col = vec3<f32>(1.0) - exp(-col);
+
+  if (u.frame_count < 5u) {
+    col = vec3<f32>(0.0);
+  }
+
+  return vec4<f32>(clamp(col, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
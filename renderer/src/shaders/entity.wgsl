@@ -1,4 +1,53 @@
+// Present pass: copies the trail texture to the screen.
+//
+// This file is also the template for custom present shaders (`present_shader` in
+// renderer.toml). A custom shader must provide `vs_main` and `fs_main` with these signatures
+// and may use these bindings:
+//
+//   @group(0) @binding(0) render_tex  Rgba32Float trail buffer (linear, unfilterable, so
+//                                     read it with textureLoad), same size as the output
+//   @group(0) @binding(1) u           Uniforms below; `resolution` is the output size in
+//                                     pixels, `time` the animation time in seconds
+//
+// On rotated outputs the buffer is drawn in the panel's native orientation: `frag_coord` and
+// `render_tex` are in buffer space, `resolution` is in the surface's (rotated) orientation,
+// and `transform` is the `wl_output.transform` between the two.
+//
+// The output is written as-is to the surface (usually sRGB), with no blending.
+
+struct Uniforms {
+  time: f32,
+  intensity: f32,
+  blend_factor: f32,
+  scale: f32,
+  current_state: u32,
+  target_state: u32,
+  frame_count: u32,
+  seed: u32,
+  resolution: vec2<f32>,
+  position: vec2<f32>,
+  damping: f32,
+  noise_strength: f32,
+  attraction: f32,
+  speed: f32,
+  trail_fade: f32,
+  glow_intensity: f32,
+  color_shift: f32,
+  transform: u32,
+  mods: vec4<f32>,
+  force_gain: vec2<f32>,
+  delta_time: f32,
+  trail_steps: u32,
+  palette_frequency: vec4<f32>,
+  palette_phase: vec4<f32>,
+  viewport: vec4<f32>,
+  particle_count: u32,
+  flock_radius: vec4<f32>,
+  flock_weight: vec4<f32>,
+};
+
 @group(0) @binding(0) var render_tex: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> u: Uniforms;
 
 @vertex
 fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
@@ -0,0 +1,384 @@
+// Simulation for the compute backend: advances every particle by one step. The forces are
+// the same as in simulation.wgsl; only the storage differs, one `Particle` per swarm member
+// instead of columns of a state texture.
+
+struct Uniforms {
+  time: f32,
+  intensity: f32,
+  blend_factor: f32,
+  scale: f32,
+  current_state: u32,
+  target_state: u32,
+  frame_count: u32,
+  seed: u32,
+  resolution: vec2<f32>,
+  position: vec2<f32>,
+  damping: f32,
+  noise_strength: f32,
+  attraction: f32,
+  speed: f32,
+  trail_fade: f32,
+  glow_intensity: f32,
+  color_shift: f32,
+  transform: u32,
+  mods: vec4<f32>,
+  force_gain: vec2<f32>,
+  delta_time: f32,
+  trail_steps: u32,
+  palette_frequency: vec4<f32>,
+  palette_phase: vec4<f32>,
+  viewport: vec4<f32>,
+  particle_count: u32,
+  flock_radius: vec4<f32>,
+  flock_weight: vec4<f32>,
+};
+
+struct Particle {
+  pos: vec4<f32>,
+  vel: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> u: Uniforms;
+@group(1) @binding(0) var<storage, read> prev: array<Particle>;
+@group(1) @binding(1) var<storage, read_write> next: array<Particle>;
+// Written by `cs_stats` from `prev`: the centroid in xyz, the spread in w.
+@group(1) @binding(2) var<storage, read_write> stats: vec4<f32>;
+// Neighbor grid for flocking, filled by `cs_bin` from `prev`: first how many particles fell into
+// each cell, then for each cell the indices of the first `CELL_CAPACITY` of them. One buffer
+// rather than two, as some adapters only allow four storage buffers.
+@group(1) @binding(3) var<storage, read_write> grid: array<atomic<u32>>;
+
+const INIT_FRAMES: u32 = 10u;
+const STATS_WORKGROUP_SIZE: u32 = 256u;
+// Cells per side of the neighbor grid, which is centered on the swarm's centroid. Cells are as
+// wide as the largest flocking radius, and particles beyond the grid share its border cells.
+const GRID_DIM: i32 = 16;
+const GRID_CELLS: u32 = 4096u;
+const CELL_CAPACITY: u32 = 32u;
+
+var<workgroup> partial_sums: array<vec4<f32>, STATS_WORKGROUP_SIZE>;
+
+fn hash3(p: vec3<f32>) -> vec3<f32> {
+  var q = fract(p * vec3<f32>(443.8975, 397.2973, 491.1871));
+  q = q + dot(q.zxy, q.yxz + vec3<f32>(19.1));
+  return fract(vec3<f32>(q.x * q.y, q.z * q.x, q.y * q.z)) - vec3<f32>(0.5);
+}
+
+fn hash1(p: vec3<f32>) -> f32 {
+  let h = dot(p, vec3<f32>(127.1, 311.7, 74.7));
+  return fract(sin(h) * 43758.5453);
+}
+
+fn noise3(p: vec3<f32>) -> f32 {
+  let i = floor(p);
+  let f = fract(p);
+  let u = f * f * (3.0 - 2.0 * f);
+
+  let n000 = hash1(i + vec3<f32>(0.0, 0.0, 0.0));
+  let n100 = hash1(i + vec3<f32>(1.0, 0.0, 0.0));
+  let n010 = hash1(i + vec3<f32>(0.0, 1.0, 0.0));
+  let n110 = hash1(i + vec3<f32>(1.0, 1.0, 0.0));
+  let n001 = hash1(i + vec3<f32>(0.0, 0.0, 1.0));
+  let n101 = hash1(i + vec3<f32>(1.0, 0.0, 1.0));
+  let n011 = hash1(i + vec3<f32>(0.0, 1.0, 1.0));
+  let n111 = hash1(i + vec3<f32>(1.0, 1.0, 1.0));
+
+  let nx00 = mix(n000, n100, u.x);
+  let nx10 = mix(n010, n110, u.x);
+  let nx01 = mix(n001, n101, u.x);
+  let nx11 = mix(n011, n111, u.x);
+  let nxy0 = mix(nx00, nx10, u.y);
+  let nxy1 = mix(nx01, nx11, u.y);
+  return mix(nxy0, nxy1, u.z);
+}
+
+fn fbm(p: vec3<f32>) -> f32 {
+  var sum = 0.0;
+  var amp = 0.5;
+  var q = p;
+  for (var i: i32 = 0; i < 3; i = i + 1) {
+    sum = sum + noise3(q) * amp;
+    q = q * 2.0 + vec3<f32>(17.0, 11.0, 5.0);
+    amp = amp * 0.5;
+  }
+  return sum;
+}
+
+fn flow_noise(p: vec3<f32>) -> vec3<f32> {
+  let n1 = fbm(p);
+  let n2 = fbm(p + vec3<f32>(11.5, 7.2, 3.4));
+  let n3 = fbm(p + vec3<f32>(5.2, 13.1, 9.7));
+  return vec3<f32>(n1, n2, n3) * 2.0 - vec3<f32>(1.0);
+}
+
+fn state_force(
+  state: u32,
+  pos: vec3<f32>,
+  vel: vec3<f32>,
+  id: f32,
+  time: f32,
+  center: vec3<f32>,
+  goal: vec3<f32>,
+) -> vec3<f32> {
+  let offset = pos - center;
+  let offset_dir = normalize(offset + vec3<f32>(0.0001));
+  let target_dir = normalize(goal - center + vec3<f32>(0.0001));
+
+  if (state == 1u) {
+    let probe = target_dir * (0.4 + 0.3 * sin(time * 0.25 + id * 0.37));
+    let stretch = offset_dir * (0.2 + 0.2 * sin(time * 0.35 + id));
+    return probe + stretch;
+  }
+  if (state == 2u) {
+    let orbit = vec3<f32>(-offset.y, offset.x, 0.0) * 0.03;
+    return orbit - vel * 0.15;
+  }
+  if (state == 3u) {
+    let dart = step(0.95, hash1(vec3<f32>(id * 1.3, time * 0.7, 4.7)));
+    let dart_dir =
+      normalize(flow_noise(pos * 2.3 + vec3<f32>(time * 0.8, id, time * 0.5)) + vec3<f32>(0.0001));
+    return dart_dir * dart * 2.6;
+  }
+  if (state == 4u) {
+    let pulse = 0.5 + 0.5 * sin(time * 1.6 + id * 0.05);
+    let burst = step(0.9, hash1(vec3<f32>(time * 0.4, id * 0.17, 2.1)));
+    return offset_dir * (1.4 * pulse + burst * 3.0);
+  }
+  if (state == 5u) {
+    let drift = offset_dir * 0.15;
+    return vec3<f32>(0.0, -0.25, 0.0) + drift;
+  }
+
+  let orbit = vec3<f32>(-offset.y, offset.x, 0.0) * 0.06;
+  let bob = vec3<f32>(0.0, sin(time * 0.2 + id * 0.5) * 0.03, 0.0);
+  return orbit + bob;
+}
+
+// Boids forces. The weights in `u.flock_weight` are scaled by these so that 1 is a noticeable
+// but not dominant force next to the flow noise.
+const SEPARATION_GAIN: f32 = 4.0;
+const ALIGNMENT_GAIN: f32 = 0.15;
+const COHESION_GAIN: f32 = 2.0;
+
+// Running sums over one particle's neighbors.
+struct Neighbors {
+  separation: vec3<f32>,
+  heading: vec3<f32>,
+  heading_count: f32,
+  center: vec3<f32>,
+  center_count: f32,
+};
+
+fn add_neighbor(
+  n: ptr<function, Neighbors>,
+  pos: vec3<f32>,
+  other_pos: vec3<f32>,
+  other_vel: vec3<f32>,
+) {
+  let offset = pos - other_pos;
+  let dist = length(offset);
+  let radius = u.flock_radius;
+  if (dist < radius.x && dist > 0.0) {
+    (*n).separation = (*n).separation + offset / dist * (1.0 - dist / radius.x);
+  }
+  if (dist < radius.y) {
+    (*n).heading = (*n).heading + other_vel;
+    (*n).heading_count = (*n).heading_count + 1.0;
+  }
+  if (dist < radius.z) {
+    (*n).center = (*n).center + other_pos;
+    (*n).center_count = (*n).center_count + 1.0;
+  }
+}
+
+fn flock_steer(n: Neighbors, pos: vec3<f32>, vel: vec3<f32>) -> vec3<f32> {
+  let weight = u.flock_weight;
+  var force = n.separation * weight.x * SEPARATION_GAIN;
+  if (n.heading_count > 0.0) {
+    force = force + (n.heading / n.heading_count - vel) * weight.y * ALIGNMENT_GAIN;
+  }
+  if (n.center_count > 0.0) {
+    let to_center = n.center / n.center_count - pos;
+    force = force + to_center / u.flock_radius.z * weight.z * COHESION_GAIN;
+  }
+  return force;
+}
+
+fn flocking() -> bool {
+  return any(u.flock_weight.xyz != vec3<f32>(0.0));
+}
+
+fn update_velocity(
+  vel: vec3<f32>,
+  pos: vec3<f32>,
+  id: f32,
+  time: f32,
+  center: vec3<f32>,
+  flock: vec3<f32>,
+) -> vec3<f32> {
+  // x: center pull, y: noise, z: goal pull, w: damping (blended on the CPU).
+  let mods = u.mods;
+  let energy = 0.35 + 0.65 * u.intensity;
+
+  var v = vel * (u.damping * mods.w);
+
+  let flow =
+    flow_noise(pos * 1.1 + vel * 0.15 + vec3<f32>(time * 0.2, time * 0.17, time * 0.13));
+  v = v + flow * (u.noise_strength * mods.y);
+
+  let aspect = u.resolution.x / max(u.resolution.y, 1.0);
+  let goal =
+    vec3<f32>((u.position - vec2<f32>(0.5, 0.5)) * vec2<f32>(aspect, 1.0) * 2.0, 0.0);
+  let to_center = center - pos;
+  let dist = length(to_center);
+  let center_dir = normalize(to_center + vec3<f32>(0.0001));
+  v = v + center_dir * dist * u.attraction * mods.x;
+
+  let to_goal = goal - pos;
+  let goal_dist = length(to_goal);
+  let goal_dir = normalize(to_goal + vec3<f32>(0.0001));
+  v = v + goal_dir * goal_dist * 0.18 * mods.z;
+
+  let state_force_cur =
+    state_force(u.current_state, pos, vel, id, time, center, goal) * u.force_gain.x;
+  let state_force_tgt =
+    state_force(u.target_state, pos, vel, id, time, center, goal) * u.force_gain.y;
+  v = v + mix(state_force_cur, state_force_tgt, u.blend_factor) * energy;
+  v = v + flock;
+
+  let boundary_center = goal;
+  let boundary_radius = 1.7;
+  let boundary_offset = pos - boundary_center;
+  let boundary_dist = length(boundary_offset);
+  if (boundary_dist > boundary_radius) {
+    let push = normalize(boundary_offset + vec3<f32>(0.0001)) * (boundary_dist - boundary_radius);
+    v = v - push * 1.4;
+  }
+
+  let speed = length(v);
+  let max_speed = 6.0;
+  if (speed > max_speed) {
+    v = v * (max_speed / speed);
+  }
+  return v;
+}
+
+fn grid_cell(pos: vec3<f32>) -> vec3<i32> {
+  let cell = floor((pos - stats.xyz) / max(u.flock_radius.w, 0.01)) + f32(GRID_DIM / 2);
+  return vec3<i32>(clamp(cell, vec3<f32>(0.0), vec3<f32>(f32(GRID_DIM - 1))));
+}
+
+fn cell_index(cell: vec3<i32>) -> u32 {
+  return u32((cell.z * GRID_DIM + cell.y) * GRID_DIM + cell.x);
+}
+
+fn grid_item(cell_id: u32, slot: u32) -> u32 {
+  return GRID_CELLS + cell_id * CELL_CAPACITY + slot;
+}
+
+// Visits the 27 cells around the particle. Crowded cells only contribute their first
+// `CELL_CAPACITY` particles, which bounds the cost in dense swarms.
+fn flock_force(index: u32, pos: vec3<f32>, vel: vec3<f32>) -> vec3<f32> {
+  var n = Neighbors(vec3<f32>(0.0), vec3<f32>(0.0), 0.0, vec3<f32>(0.0), 0.0);
+  let home = grid_cell(pos);
+  for (var z = -1; z <= 1; z = z + 1) {
+    for (var y = -1; y <= 1; y = y + 1) {
+      for (var x = -1; x <= 1; x = x + 1) {
+        let cell = home + vec3<i32>(x, y, z);
+        if (any(cell < vec3<i32>(0)) || any(cell >= vec3<i32>(GRID_DIM))) {
+          continue;
+        }
+        let cell_id = cell_index(cell);
+        let count = min(atomicLoad(&grid[cell_id]), CELL_CAPACITY);
+        for (var i = 0u; i < count; i = i + 1u) {
+          let other = atomicLoad(&grid[grid_item(cell_id, i)]);
+          if (other == index) {
+            continue;
+          }
+          add_neighbor(&n, pos, prev[other].pos.xyz, prev[other].vel.xyz);
+        }
+      }
+    }
+  }
+  return flock_steer(n, pos, vel);
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+  let count = arrayLength(&prev);
+  let index = id.x;
+  if (index >= count) {
+    return;
+  }
+
+  if (u.frame_count < INIT_FRAMES) {
+    // Same hashes as the fragment backend's velocity row 0 and position row 100.
+    let x = f32(index) / f32(count);
+    let vel = hash3(vec3<f32>(vec2<f32>(x, 0.0) * 1.9, f32(u.seed)));
+    let pos = hash3(vec3<f32>(vec2<f32>(x, 100.0 / 128.0) * 1.9, f32(u.seed)));
+    next[index] = Particle(vec4<f32>(pos * 0.5, 1.0), vec4<f32>(vel * 10.0, 1.0));
+    return;
+  }
+
+  let pos = prev[index].pos.xyz;
+  let vel = prev[index].vel.xyz;
+  let swarm_center = stats.xyz;
+  var flock = vec3<f32>(0.0);
+  if (flocking()) {
+    flock = flock_force(index, pos, vel);
+  }
+  let new_vel = update_velocity(vel, pos, f32(index), u.time, swarm_center, flock);
+  // Forces are tuned per 1/60 s step, which is what the renderer always steps by; only the
+  // integration follows `delta_time`.
+  let new_pos = pos + new_vel * (0.002 * u.speed * u.delta_time * 60.0);
+  next[index] = Particle(vec4<f32>(new_pos, 1.0), vec4<f32>(new_vel, 1.0));
+}
+
+// Statistics pass, dispatched as a single workgroup before each step: the centroid of all
+// particles and their RMS distance from it (the spread). Each invocation sums a strided share
+// of the particles, then the partial sums are added up pairwise.
+@compute @workgroup_size(256)
+fn cs_stats(@builtin(local_invocation_index) index: u32) {
+  let count = arrayLength(&prev);
+  var sum = vec4<f32>(0.0);
+  for (var i = index; i < count; i = i + STATS_WORKGROUP_SIZE) {
+    let pos = prev[i].pos.xyz;
+    sum = sum + vec4<f32>(pos, dot(pos, pos));
+  }
+  partial_sums[index] = sum;
+  workgroupBarrier();
+
+  for (var stride = STATS_WORKGROUP_SIZE / 2u; stride > 0u; stride = stride / 2u) {
+    if (index < stride) {
+      partial_sums[index] = partial_sums[index] + partial_sums[index + stride];
+    }
+    workgroupBarrier();
+  }
+
+  if (index == 0u) {
+    let center = partial_sums[0].xyz / f32(count);
+    let spread = sqrt(max(partial_sums[0].w / f32(count) - dot(center, center), 0.0));
+    stats = vec4<f32>(center, spread);
+  }
+}
+
+// The two flocking passes, dispatched between `cs_stats` and `cs_main` while flocking is on.
+@compute @workgroup_size(64)
+fn cs_clear_grid(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (id.x < GRID_CELLS) {
+    atomicStore(&grid[id.x], 0u);
+  }
+}
+
+@compute @workgroup_size(64)
+fn cs_bin(@builtin(global_invocation_id) id: vec3<u32>) {
+  let index = id.x;
+  if (index >= arrayLength(&prev)) {
+    return;
+  }
+  let cell_id = cell_index(grid_cell(prev[index].pos.xyz));
+  let slot = atomicAdd(&grid[cell_id], 1u);
+  if (slot < CELL_CAPACITY) {
+    atomicStore(&grid[grid_item(cell_id, slot)], index);
+  }
+}
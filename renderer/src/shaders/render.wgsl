@@ -6,7 +6,7 @@ struct Uniforms {
   current_state: u32,
   target_state: u32,
   frame_count: u32,
-  _pad0: u32,
+  seed: u32,
   resolution: vec2<f32>,
   position: vec2<f32>,
   damping: f32,
@@ -16,12 +16,22 @@ struct Uniforms {
   trail_fade: f32,
   glow_intensity: f32,
   color_shift: f32,
-  _pad1: f32,
+  transform: u32,
+  mods: vec4<f32>,
+  force_gain: vec2<f32>,
+  delta_time: f32,
+  trail_steps: u32,
+  palette_frequency: vec4<f32>,
+  palette_phase: vec4<f32>,
+  viewport: vec4<f32>,
+  particle_count: u32,
+  flock_radius: vec4<f32>,
+  flock_weight: vec4<f32>,
 };
 
 @group(0) @binding(0) var<uniform> u: Uniforms;
 @group(1) @binding(0) var state_tex: texture_2d<f32>;
-@group(1) @binding(1) var prev_render: texture_2d<f32>;
+@group(2) @binding(0) var prev_render: texture_2d<f32>;
 
 @vertex
 fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
@@ -34,8 +44,6 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<
 }
 
 const TAU: f32 = 6.2831853;
-const NUM_PARTICLES: u32 = 70u;    // Reduced from 140 for performance
-const STEPS_PER_FRAME: u32 = 4u;   // Reduced from 7 for performance
 const POS_SAMPLE_ROW: i32 = 100;
 
 fn mag(p: vec3<f32>) -> f32 {
@@ -47,13 +55,19 @@ fn draw_particles(ro: vec3<f32>, rd: vec3<f32>) -> vec3<f32> {
   let dims = textureDimensions(state_tex);
   let pos_row = min(POS_SAMPLE_ROW, i32(dims.y) - 1);
   let vel_row = 0;
-
-  for (var i = 0u; i < NUM_PARTICLES; i = i + 1u) {
+  // The state texture has one column per particle.
+  let particle_count = dims.x;
+  let steps = max(u.trail_steps, 1u);
+  // 0.08 per particle at the default 70, scaled so that the overall brightness does not
+  // depend on the particle count.
+  let weight = 0.08 * 70.0 / f32(particle_count);
+
+  for (var i = 0u; i < particle_count; i = i + 1u) {
     let pos = textureLoad(state_tex, vec2<i32>(i32(i), pos_row), 0).xyz;
     let vel = textureLoad(state_tex, vec2<i32>(i32(i), vel_row), 0).xyz;
     var step_pos = pos;
 
-    for (var j = 0u; j < STEPS_PER_FRAME; j = j + 1u) {
+    for (var j = 0u; j < steps; j = j + 1u) {
       let t = dot(step_pos - ro, rd);
       let closest = ro + rd * t;
       var d = mag(closest - step_pos);
@@ -61,23 +75,42 @@ fn draw_particles(ro: vec3<f32>, rd: vec3<f32>) -> vec3<f32> {
 
       let phase = u.time * 0.06 + f32(i) * 0.003 + 2.0 + u.color_shift * TAU;
       let color = abs(
-        sin(vec3<f32>(2.0, 3.4, 1.2) * phase + vec3<f32>(0.8, 0.0, 1.2)) * 0.7 + 0.3
+        sin(u.palette_frequency.xyz * phase + u.palette_phase.xyz) * u.palette_frequency.w
+          + u.palette_phase.w
       );
-      rez = rez + d * color * 0.08;  // Boosted to compensate for fewer particles
+      rez = rez + d * color * weight;
       step_pos = step_pos + vel * (0.002 * 0.2 * u.speed);
     }
   }
 
-  rez = rez / f32(STEPS_PER_FRAME);
+  rez = rez / f32(steps);
   return rez;
 }
 
+// Maps a position in the buffer, in 0..1, to where it appears on the surface. `transform` is
+// the `wl_output.transform` the buffer was drawn with.
+fn buffer_to_surface(b: vec2<f32>, transform: u32) -> vec2<f32> {
+  switch transform {
+    case 1u: { return vec2<f32>(b.y, 1.0 - b.x); }
+    case 2u: { return vec2<f32>(1.0 - b.x, 1.0 - b.y); }
+    case 3u: { return vec2<f32>(1.0 - b.y, b.x); }
+    case 4u: { return vec2<f32>(1.0 - b.x, b.y); }
+    case 5u: { return vec2<f32>(b.y, b.x); }
+    case 6u: { return vec2<f32>(b.x, 1.0 - b.y); }
+    case 7u: { return vec2<f32>(1.0 - b.y, 1.0 - b.x); }
+    default: { return b; }
+  }
+}
+
 @fragment
 fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
   let dims = textureDimensions(prev_render);
-  let res = vec2<f32>(f32(dims.x), f32(dims.y));
-  var p = frag_coord.xy / res - vec2<f32>(0.5, 0.5);
-  let aspect = res.x / res.y;
+  let uv = buffer_to_surface(frag_coord.xy / vec2<f32>(f32(dims.x), f32(dims.y)), u.transform);
+  // Position on the swarm's canvas, which may span several outputs; this output shows the
+  // `viewport` part of it.
+  var p = u.viewport.xy + uv * u.viewport.zw - vec2<f32>(0.5, 0.5);
+  let canvas = u.resolution / u.viewport.zw;
+  let aspect = canvas.x / canvas.y;
   p.x = p.x * aspect;
 
   let center_offset = (u.position - vec2<f32>(0.5, 0.5)) * vec2<f32>(aspect, 1.0);
@@ -92,7 +125,10 @@ fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
   let coord = vec2<i32>(i32(frag_coord.x), i32(frag_coord.y));
   let colb = textureLoad(prev_render, coord, 0).xyz;
 
-  var col = (cola + colb) * u.trail_fade;
+  // `trail_fade` is per 1/60 s; scale the fade and the new light by the time this frame
+  // covers so trails look the same at any frame rate.
+  let frames = u.delta_time * 60.0;
+  var col = (cola * frames + colb) * pow(u.trail_fade, frames);
   col = vec3<f32>(1.0) - exp(-col);
 
   if (u.frame_count < 5u) {
@@ -6,7 +6,7 @@ struct Uniforms {
   current_state: u32,
   target_state: u32,
   frame_count: u32,
-  _pad0: u32,
+  seed: u32,
   resolution: vec2<f32>,
   position: vec2<f32>,
   damping: f32,
@@ -16,11 +16,23 @@ struct Uniforms {
   trail_fade: f32,
   glow_intensity: f32,
   color_shift: f32,
-  _pad1: f32,
+  transform: u32,
+  mods: vec4<f32>,
+  force_gain: vec2<f32>,
+  delta_time: f32,
+  trail_steps: u32,
+  palette_frequency: vec4<f32>,
+  palette_phase: vec4<f32>,
+  viewport: vec4<f32>,
+  particle_count: u32,
+  flock_radius: vec4<f32>,
+  flock_weight: vec4<f32>,
 };
 
 @group(0) @binding(0) var<uniform> u: Uniforms;
 @group(1) @binding(0) var prev_state: texture_2d<f32>;
+// Output of `fs_stats` for `prev_state`: the centroid in xyz, the spread in w.
+@group(2) @binding(0) var stats_tex: texture_2d<f32>;
 
 @vertex
 fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
@@ -89,40 +101,6 @@ fn flow_noise(p: vec3<f32>) -> vec3<f32> {
   return vec3<f32>(n1, n2, n3) * 2.0 - vec3<f32>(1.0);
 }
 
-fn sample_swarm_center(pos_row: i32) -> vec3<f32> {
-  let dims = textureDimensions(prev_state);
-  let max_index = i32(dims.x) - 1;
-  var acc = vec3<f32>(0.0);
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(0, max_index), pos_row), 0).xyz;
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(9, max_index), pos_row), 0).xyz;
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(18, max_index), pos_row), 0).xyz;
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(27, max_index), pos_row), 0).xyz;
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(36, max_index), pos_row), 0).xyz;
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(45, max_index), pos_row), 0).xyz;
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(54, max_index), pos_row), 0).xyz;
-  acc = acc + textureLoad(prev_state, vec2<i32>(min(63, max_index), pos_row), 0).xyz;
-  return acc / 8.0;
-}
-
-fn state_mods(state: u32) -> vec4<f32> {
-  if (state == 1u) {
-    return vec4<f32>(0.45, 1.2, 0.9, 1.0);
-  }
-  if (state == 2u) {
-    return vec4<f32>(1.8, 0.25, 0.2, 0.85);
-  }
-  if (state == 3u) {
-    return vec4<f32>(0.6, 1.4, 0.5, 0.95);
-  }
-  if (state == 4u) {
-    return vec4<f32>(-0.4, 1.6, 0.2, 0.95);
-  }
-  if (state == 5u) {
-    return vec4<f32>(0.15, 0.2, 0.1, 0.75);
-  }
-  return vec4<f32>(0.55, 0.9, 0.35, 1.0);
-}
-
 fn state_force(
   state: u32,
   pos: vec3<f32>,
@@ -166,16 +144,70 @@ fn state_force(
   return orbit + bob;
 }
 
+// Boids forces. The weights in `u.flock_weight` are scaled by these so that 1 is a noticeable
+// but not dominant force next to the flow noise.
+const SEPARATION_GAIN: f32 = 4.0;
+const ALIGNMENT_GAIN: f32 = 0.15;
+const COHESION_GAIN: f32 = 2.0;
+
+// Running sums over one particle's neighbors.
+struct Neighbors {
+  separation: vec3<f32>,
+  heading: vec3<f32>,
+  heading_count: f32,
+  center: vec3<f32>,
+  center_count: f32,
+};
+
+fn add_neighbor(
+  n: ptr<function, Neighbors>,
+  pos: vec3<f32>,
+  other_pos: vec3<f32>,
+  other_vel: vec3<f32>,
+) {
+  let offset = pos - other_pos;
+  let dist = length(offset);
+  let radius = u.flock_radius;
+  if (dist < radius.x && dist > 0.0) {
+    (*n).separation = (*n).separation + offset / dist * (1.0 - dist / radius.x);
+  }
+  if (dist < radius.y) {
+    (*n).heading = (*n).heading + other_vel;
+    (*n).heading_count = (*n).heading_count + 1.0;
+  }
+  if (dist < radius.z) {
+    (*n).center = (*n).center + other_pos;
+    (*n).center_count = (*n).center_count + 1.0;
+  }
+}
+
+fn flock_steer(n: Neighbors, pos: vec3<f32>, vel: vec3<f32>) -> vec3<f32> {
+  let weight = u.flock_weight;
+  var force = n.separation * weight.x * SEPARATION_GAIN;
+  if (n.heading_count > 0.0) {
+    force = force + (n.heading / n.heading_count - vel) * weight.y * ALIGNMENT_GAIN;
+  }
+  if (n.center_count > 0.0) {
+    let to_center = n.center / n.center_count - pos;
+    force = force + to_center / u.flock_radius.z * weight.z * COHESION_GAIN;
+  }
+  return force;
+}
+
+fn flocking() -> bool {
+  return any(u.flock_weight.xyz != vec3<f32>(0.0));
+}
+
 fn update_velocity(
   vel: vec3<f32>,
   pos: vec3<f32>,
   id: f32,
   time: f32,
   center: vec3<f32>,
+  flock: vec3<f32>,
 ) -> vec3<f32> {
-  let mods_cur = state_mods(u.current_state);
-  let mods_tgt = state_mods(u.target_state);
-  let mods = mix(mods_cur, mods_tgt, u.blend_factor);
+  // x: center pull, y: noise, z: goal pull, w: damping (blended on the CPU).
+  let mods = u.mods;
   let energy = 0.35 + 0.65 * u.intensity;
 
   var v = vel * (u.damping * mods.w);
@@ -197,9 +229,12 @@ fn update_velocity(
   let goal_dir = normalize(to_goal + vec3<f32>(0.0001));
   v = v + goal_dir * goal_dist * 0.18 * mods.z;
 
-  let state_force_cur = state_force(u.current_state, pos, vel, id, time, center, goal);
-  let state_force_tgt = state_force(u.target_state, pos, vel, id, time, center, goal);
+  let state_force_cur =
+    state_force(u.current_state, pos, vel, id, time, center, goal) * u.force_gain.x;
+  let state_force_tgt =
+    state_force(u.target_state, pos, vel, id, time, center, goal) * u.force_gain.y;
   v = v + mix(state_force_cur, state_force_tgt, u.blend_factor) * energy;
+  v = v + flock;
 
   let boundary_center = goal;
   let boundary_radius = 1.7;
@@ -218,6 +253,22 @@ fn update_velocity(
   return v;
 }
 
+// Brute force over the whole swarm: the fragment backend is limited to a couple of thousand
+// particles anyway.
+fn flock_force(index: i32, pos: vec3<f32>, vel: vec3<f32>, pos_row: i32) -> vec3<f32> {
+  var n = Neighbors(vec3<f32>(0.0), vec3<f32>(0.0), 0.0, vec3<f32>(0.0), 0.0);
+  let count = i32(textureDimensions(prev_state).x);
+  for (var i = 0; i < count; i = i + 1) {
+    if (i == index) {
+      continue;
+    }
+    let other_pos = textureLoad(prev_state, vec2<i32>(i, pos_row), 0).xyz;
+    let other_vel = textureLoad(prev_state, vec2<i32>(i, 0), 0).xyz;
+    add_neighbor(&n, pos, other_pos, other_vel);
+  }
+  return flock_steer(n, pos, vel);
+}
+
 @fragment
 fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
   let dims = textureDimensions(prev_state);
@@ -229,7 +280,7 @@ fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
   if (u.frame_count < INIT_FRAMES) {
     let dims_f = vec2<f32>(f32(dims.x), f32(dims.y));
     let q = vec2<f32>(f32(coord.x), f32(coord.y)) / dims_f;
-    let noise = hash3(vec3<f32>(q * 1.9, 0.0));
+    let noise = hash3(vec3<f32>(q * 1.9, f32(u.seed)));
     if (coord.y < VELOCITY_ROWS) {
       return vec4<f32>(noise * 10.0, 1.0);
     } else {
@@ -242,12 +293,37 @@ fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
   let pos = textureLoad(prev_state, vec2<i32>(coord.x, pos_row), 0).xyz;
   let vel = textureLoad(prev_state, vec2<i32>(coord.x, vel_row), 0).xyz;
 
-  let swarm_center = sample_swarm_center(pos_row);
-  let new_vel = update_velocity(vel, pos, f32(coord.x), u.time, swarm_center);
-  let new_pos = pos + new_vel * (0.002 * u.speed);
+  let swarm_center = textureLoad(stats_tex, vec2<i32>(0, 0), 0).xyz;
+  // Only rows 0 and `pos_row` are ever read back, so the others skip the costly neighbor scan.
+  var flock = vec3<f32>(0.0);
+  if (flocking() && (coord.y == vel_row || coord.y == pos_row)) {
+    flock = flock_force(coord.x, pos, vel, pos_row);
+  }
+  let new_vel = update_velocity(vel, pos, f32(coord.x), u.time, swarm_center, flock);
+  // Forces are tuned per 1/60 s step, which is what the renderer always steps by; only the
+  // integration follows `delta_time`.
+  let new_pos = pos + new_vel * (0.002 * u.speed * u.delta_time * 60.0);
 
   if (coord.y < VELOCITY_ROWS) {
     return vec4<f32>(new_vel, 1.0);
   }
   return vec4<f32>(new_pos, 1.0);
 }
+
+// Statistics pass, drawn into a single texel before each step: the centroid of all particles
+// in xyz and their RMS distance from it (the spread) in w.
+@fragment
+fn fs_stats() -> @location(0) vec4<f32> {
+  let dims = textureDimensions(prev_state);
+  let pos_row = min(POS_SAMPLE_ROW, i32(dims.y) - 1);
+  var sum = vec3<f32>(0.0);
+  var sum_sq = 0.0;
+  for (var i = 0; i < i32(dims.x); i = i + 1) {
+    let pos = textureLoad(prev_state, vec2<i32>(i, pos_row), 0).xyz;
+    sum = sum + pos;
+    sum_sq = sum_sq + dot(pos, pos);
+  }
+  let count = f32(dims.x);
+  let center = sum / count;
+  return vec4<f32>(center, sqrt(max(sum_sq / count - dot(center, center), 0.0)));
+}
@@ -0,0 +1,148 @@
+// Particle drawing for the compute backend: one instanced quad per particle, covering the
+// part of the screen its glow reaches, additively blended into an Rgba16Float light buffer.
+// Each fragment evaluates the same glow as render.wgsl, but only for the particles whose
+// quads cover it; composite.wgsl turns the light into trails.
+
+struct Uniforms {
+  time: f32,
+  intensity: f32,
+  blend_factor: f32,
+  scale: f32,
+  current_state: u32,
+  target_state: u32,
+  frame_count: u32,
+  seed: u32,
+  resolution: vec2<f32>,
+  position: vec2<f32>,
+  damping: f32,
+  noise_strength: f32,
+  attraction: f32,
+  speed: f32,
+  trail_fade: f32,
+  glow_intensity: f32,
+  color_shift: f32,
+  transform: u32,
+  mods: vec4<f32>,
+  force_gain: vec2<f32>,
+  delta_time: f32,
+  trail_steps: u32,
+  palette_frequency: vec4<f32>,
+  palette_phase: vec4<f32>,
+  viewport: vec4<f32>,
+  particle_count: u32,
+  flock_radius: vec4<f32>,
+  flock_weight: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> u: Uniforms;
+
+const TAU: f32 = 6.2831853;
+const CAMERA_Z: f32 = 2.5;
+// World-space distance from a particle beyond which its glow is cut off. The glow is offset
+// so that it reaches zero there instead of ending at the quad's edge.
+const GLOW_RADIUS: f32 = 0.3;
+
+struct VertexOutput {
+  @builtin(position) clip: vec4<f32>,
+  // Position in the same camera plane render.wgsl casts its rays through.
+  @location(0) p: vec2<f32>,
+  @location(1) @interpolate(flat) pos: vec3<f32>,
+  @location(2) @interpolate(flat) vel: vec3<f32>,
+  @location(3) @interpolate(flat) id: u32,
+};
+
+fn mag(p: vec3<f32>) -> f32 {
+  return dot(p, p);
+}
+
+fn glow(d: f32) -> f32 {
+  return 0.14 / (pow(d * 1000.0, 1.1) + 0.03);
+}
+
+// Where the ray through a world position meets the camera plane.
+fn project(pos: vec3<f32>) -> vec2<f32> {
+  return pos.xy * 0.5 / max(CAMERA_Z - pos.z, 0.1);
+}
+
+// Inverse of `buffer_to_surface` in render.wgsl: maps a position on the surface, in 0..1, to
+// where it is in the buffer.
+fn surface_to_buffer(s: vec2<f32>, transform: u32) -> vec2<f32> {
+  switch transform {
+    case 1u: { return vec2<f32>(1.0 - s.y, s.x); }
+    case 2u: { return vec2<f32>(1.0 - s.x, 1.0 - s.y); }
+    case 3u: { return vec2<f32>(s.y, 1.0 - s.x); }
+    case 4u: { return vec2<f32>(1.0 - s.x, s.y); }
+    case 5u: { return vec2<f32>(s.y, s.x); }
+    case 6u: { return vec2<f32>(s.x, 1.0 - s.y); }
+    case 7u: { return vec2<f32>(1.0 - s.y, 1.0 - s.x); }
+    default: { return s; }
+  }
+}
+
+// Inverse of the mapping from the output's pixels to the camera plane in render.wgsl.
+fn plane_to_clip(p: vec2<f32>) -> vec4<f32> {
+  let canvas = u.resolution / u.viewport.zw;
+  let aspect = canvas.x / canvas.y;
+  let center_offset = (u.position - vec2<f32>(0.5, 0.5)) * vec2<f32>(aspect, 1.0);
+  let q = p * max(u.scale, 0.001) + center_offset;
+  let canvas_uv = vec2<f32>(q.x / aspect, q.y) + vec2<f32>(0.5, 0.5);
+  let uv = (canvas_uv - u.viewport.xy) / u.viewport.zw;
+  let b = surface_to_buffer(uv, u.transform);
+  return vec4<f32>(b.x * 2.0 - 1.0, 1.0 - b.y * 2.0, 0.0, 1.0);
+}
+
+fn trail_step() -> f32 {
+  return 0.002 * 0.2 * u.speed;
+}
+
+@vertex
+fn vs_main(
+  @builtin(vertex_index) vertex_index: u32,
+  @builtin(instance_index) instance_index: u32,
+  @location(0) pos: vec4<f32>,
+  @location(1) vel: vec4<f32>,
+) -> VertexOutput {
+  let steps = max(u.trail_steps, 1u);
+  let head = project(pos.xyz);
+  let tail = project(pos.xyz + vel.xyz * (trail_step() * f32(steps - 1u)));
+  // A ray misses a point by at least its offset in the plane scaled by the ray's slope, so
+  // this half-size holds the whole glow for rays anywhere near the particle.
+  let depth = max(CAMERA_Z - pos.z, 0.1);
+  let radius = GLOW_RADIUS * sqrt(dot(head, head) + 1.0) / depth;
+  let half_size = abs(tail - head) * 0.5 + vec2<f32>(radius);
+  let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u)) * 2.0 - 1.0;
+  let p = (head + tail) * 0.5 + corner * half_size;
+
+  var out: VertexOutput;
+  out.clip = plane_to_clip(p);
+  out.p = p;
+  out.pos = pos.xyz;
+  out.vel = vel.xyz;
+  out.id = instance_index;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let ro = vec3<f32>(0.0, 0.0, CAMERA_Z);
+  let rd = normalize(vec3<f32>(in.p, -0.5));
+  let steps = max(u.trail_steps, 1u);
+  let weight = 0.08 * 70.0 / f32(max(u.particle_count, 1u));
+
+  let phase = u.time * 0.06 + f32(in.id) * 0.003 + 2.0 + u.color_shift * TAU;
+  let color = abs(
+    sin(u.palette_frequency.xyz * phase + u.palette_phase.xyz) * u.palette_frequency.w
+      + u.palette_phase.w
+  );
+  let cutoff = glow(GLOW_RADIUS * GLOW_RADIUS);
+
+  var rez = 0.0;
+  var step_pos = in.pos;
+  for (var j = 0u; j < steps; j = j + 1u) {
+    let t = dot(step_pos - ro, rd);
+    let closest = ro + rd * t;
+    rez = rez + max(glow(mag(closest - step_pos)) - cutoff, 0.0);
+    step_pos = step_pos + in.vel * trail_step();
+  }
+  return vec4<f32>(color * rez * weight / f32(steps), 1.0);
+}
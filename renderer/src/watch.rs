@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io,
+    os::fd::{AsFd, OwnedFd},
+    path::{Path, PathBuf},
+};
+
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction, RegistrationToken};
+use inotify::{Inotify, WatchMask};
+use log::{debug, warn};
+
+/// Watches individual files through inotify watches on their parent directories, so both
+/// in-place writes and editors that save by renaming a temp file over the original are seen.
+pub struct FileWatcher {
+    inotify: Inotify,
+    /// Watched file names per watched directory.
+    dirs: HashMap<inotify::WatchDescriptor, (PathBuf, Vec<OsString>)>,
+}
+
+impl FileWatcher {
+    /// Files whose parent directory doesn't exist are skipped with a log message.
+    pub fn new<'a>(files: impl IntoIterator<Item = &'a Path>) -> io::Result<Self> {
+        let inotify = Inotify::init()?;
+        let mut watcher = Self {
+            inotify,
+            dirs: HashMap::new(),
+        };
+        for file in files {
+            watcher.add(file)?;
+        }
+        Ok(watcher)
+    }
+
+    fn add(&mut self, file: &Path) -> io::Result<()> {
+        let (Some(dir), Some(name)) = (file.parent(), file.file_name()) else {
+            warn!("Cannot watch {}: not a file path", file.display());
+            return Ok(());
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+
+        let mask = WatchMask::CLOSE_WRITE
+            | WatchMask::MOVED_TO
+            | WatchMask::MOVED_FROM
+            | WatchMask::DELETE;
+        let wd = match self.inotify.watches().add(dir, mask) {
+            Ok(wd) => wd,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                debug!(
+                    "Not watching {}: {} does not exist",
+                    file.display(),
+                    dir.display()
+                );
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+
+        let (_, names) = self
+            .dirs
+            .entry(wd)
+            .or_insert_with(|| (dir.to_path_buf(), Vec::new()));
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_os_string());
+        }
+        debug!("Watching {}", file.display());
+        Ok(())
+    }
+
+    /// Drains pending events and returns the watched files they touched, without duplicates.
+    fn changed_files(&mut self) -> Vec<PathBuf> {
+        let mut buffer = [0u8; 4096];
+        let mut changed = Vec::new();
+        loop {
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    warn!("inotify read error: {err}");
+                    break;
+                }
+            };
+            for event in events {
+                let (Some(name), Some((dir, names))) = (event.name, self.dirs.get(&event.wd))
+                else {
+                    continue;
+                };
+                if names.iter().any(|n| n == name) {
+                    let path = dir.join(name);
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    /// Registers the watcher with the event loop; `on_change` gets the files that changed
+    /// since the last call.
+    pub fn register<D: 'static>(
+        mut self,
+        handle: &LoopHandle<'static, D>,
+        mut on_change: impl FnMut(Vec<PathBuf>, &mut D) + 'static,
+    ) -> anyhow::Result<RegistrationToken> {
+        // The event source only needs readiness; the watcher itself stays in the closure so
+        // it can be read mutably.
+        let fd: OwnedFd = self.inotify.as_fd().try_clone_to_owned()?;
+        let token = handle
+            .insert_source(
+                Generic::new(fd, Interest::READ, Mode::Level),
+                move |_, _, data| {
+                    let changed = self.changed_files();
+                    if !changed.is_empty() {
+                        on_change(changed, data);
+                    }
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|err| anyhow::anyhow!("failed to register file watcher: {err}"))?;
+        Ok(token)
+    }
+}